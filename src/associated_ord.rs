@@ -11,7 +11,7 @@ impl<T, O: Ord> Ord for AssociatedOrd<T, O> {
 
 impl<T, O: Ord> PartialOrd for AssociatedOrd<T, O> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.ord.partial_cmp(&other.ord)
+        Some(self.cmp(other))
     }
 }
 
@@ -0,0 +1,75 @@
+use ilattice::prelude::Extent;
+
+use crate::prelude::*;
+
+use super::history::EditHistory;
+
+/// A copied region of the world that can be transformed and pasted elsewhere.
+#[derive(Default, Resource)]
+pub struct Clipboard {
+    volume: Option<VoxelVolume>,
+}
+
+impl Clipboard {
+    #[inline]
+    pub fn get(&self) -> Option<&VoxelVolume> {
+        self.volume.as_ref()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.volume.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.volume = None;
+    }
+
+    /// Copies the voxels within a world extent, replacing the current contents.
+    pub fn copy(&mut self, world: &VoxelWorld, extent: Extent<IVec3>) {
+        self.volume = Some(world.get_voxels(extent));
+    }
+
+    /// Copies the voxels within a world extent and clears them, recording the edit in `history`.
+    pub fn cut(
+        &mut self,
+        history: &mut EditHistory,
+        world: &mut VoxelWorld,
+        extent: Extent<IVec3>,
+    ) {
+        self.copy(world, extent);
+        history.fill_voxels(world, extent, Voxel::EMPTY);
+    }
+
+    /// Rotates the contents by a number of quarter turns around an axis. See
+    /// [`VoxelVolume::rotated`].
+    pub fn rotate(&mut self, axis: Axis3, quarter_turns: i32) {
+        if let Some(volume) = &mut self.volume {
+            *volume = volume.rotated(axis, quarter_turns);
+        }
+    }
+
+    pub fn mirror(&mut self, axis: Axis3) {
+        if let Some(volume) = &mut self.volume {
+            *volume = volume.mirrored(axis);
+        }
+    }
+
+    /// Pastes the contents with their minimum corner at `minimum`, recording the edit in
+    /// `history`. Returns the extent that was written, or `None` if the clipboard is empty.
+    pub fn paste(
+        &self,
+        history: &mut EditHistory,
+        world: &mut VoxelWorld,
+        minimum: WorldVoxelPos,
+    ) -> Option<Extent<IVec3>> {
+        let volume = self.volume.as_ref()?;
+
+        history.set_voxels(world, minimum, volume);
+
+        Some(Extent::from_min_and_shape(
+            minimum.0,
+            volume.shape().as_ivec3(),
+        ))
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::VecDeque, mem};
+
+use ilattice::prelude::Extent;
+
+use crate::prelude::*;
+
+/// The default memory budget of an [`EditHistory`], in bytes.
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// The voxels of an extent as they were before an edit.
+struct EditRecord {
+    minimum: WorldVoxelPos,
+    voxels: VoxelVolume,
+}
+
+impl EditRecord {
+    fn capture(world: &VoxelWorld, extent: Extent<IVec3>) -> Self {
+        Self {
+            minimum: WorldVoxelPos(extent.minimum),
+            voxels: world.get_voxels(extent),
+        }
+    }
+
+    fn extent(&self) -> Extent<IVec3> {
+        Extent::from_min_and_shape(self.minimum.0, self.voxels.shape().as_ivec3())
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of_val(self.voxels.read_data())
+    }
+}
+
+/// A group of edits that are undone and redone together.
+#[derive(Default)]
+struct EditTransaction {
+    records: Vec<EditRecord>,
+    size: usize,
+}
+
+impl EditTransaction {
+    fn push(&mut self, record: EditRecord) {
+        self.size += record.size();
+        self.records.push(record);
+    }
+
+    /// Restores every record in reverse order, returning a transaction that reverts the restore.
+    fn apply(self, world: &mut VoxelWorld) -> Self {
+        let mut inverse = Self::default();
+
+        for record in self.records.into_iter().rev() {
+            inverse.push(EditRecord::capture(world, record.extent()));
+            world.set_voxels(record.minimum, &record.voxels);
+        }
+
+        inverse
+    }
+}
+
+/// An undo/redo log of edits made to the [`VoxelWorld`].
+///
+/// Edits made through the history record the voxels they overwrite. Edits between
+/// [`EditHistory::begin`] and [`EditHistory::commit`] are grouped into a single transaction;
+/// other edits are each a transaction of their own. Undoing and redoing writes through
+/// [`VoxelWorld::set_voxels`], so touched chunks are remeshed as usual. Voxels in chunks that are
+/// not loaded are not restored.
+///
+/// The oldest transactions are discarded once the recorded voxels exceed the memory budget, so a
+/// single transaction larger than the budget cannot be undone once it is committed.
+#[derive(Resource)]
+pub struct EditHistory {
+    undo: VecDeque<EditTransaction>,
+    redo: Vec<EditTransaction>,
+    open: Option<EditTransaction>,
+    memory_budget: usize,
+    memory_used: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_memory_budget(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl EditHistory {
+    pub fn with_memory_budget(memory_budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            memory_budget,
+            memory_used: 0,
+        }
+    }
+
+    #[inline]
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.enforce_budget();
+    }
+
+    /// Returns the number of bytes of voxels currently recorded.
+    #[inline]
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Starts grouping edits into a single transaction, committing any open one first.
+    pub fn begin(&mut self) {
+        self.commit();
+        self.open = Some(EditTransaction::default());
+    }
+
+    /// Ends the open transaction, making it available to undo.
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.open.take() {
+            // The open transaction is already counted; push_undo counts it again.
+            self.memory_used -= transaction.size;
+            self.push_undo(transaction);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.memory_used = 0;
+    }
+
+    /// Sets a voxel, recording its previous value. See [`VoxelWorld::set_voxel`].
+    pub fn set_voxel(&mut self, world: &mut VoxelWorld, position: WorldVoxelPos, voxel: Voxel) {
+        self.record(world, Extent::from_min_and_shape(position.0, IVec3::ONE));
+        world.set_voxel(position, voxel);
+    }
+
+    /// Fills an extent, recording its previous voxels. See [`VoxelWorld::fill_voxels`].
+    pub fn fill_voxels(&mut self, world: &mut VoxelWorld, extent: Extent<IVec3>, voxel: Voxel) {
+        self.record(world, extent);
+        world.fill_voxels(extent, voxel);
+    }
+
+    /// Copies a volume into the world, recording the voxels it overwrites. See
+    /// [`VoxelWorld::set_voxels`].
+    pub fn set_voxels(
+        &mut self,
+        world: &mut VoxelWorld,
+        minimum: WorldVoxelPos,
+        volume: &VoxelVolume,
+    ) {
+        self.record(
+            world,
+            Extent::from_min_and_shape(minimum.0, volume.shape().as_ivec3()),
+        );
+        world.set_voxels(minimum, volume);
+    }
+
+    /// Copies the non-empty voxels of a volume into the world, recording the voxels it
+    /// overwrites. See [`VoxelWorld::merge_voxels`].
+    pub fn merge_voxels(
+        &mut self,
+        world: &mut VoxelWorld,
+        minimum: WorldVoxelPos,
+        volume: &VoxelVolume,
+    ) {
+        self.record(
+            world,
+            Extent::from_min_and_shape(minimum.0, volume.shape().as_ivec3()),
+        );
+        world.merge_voxels(minimum, volume);
+    }
+
+    /// Sets every voxel in an extent from `f`, recording its previous voxels. See
+    /// [`VoxelWorld::set_voxels_with`].
+    pub fn set_voxels_with(
+        &mut self,
+        world: &mut VoxelWorld,
+        extent: Extent<IVec3>,
+        f: impl FnMut(WorldVoxelPos) -> Voxel,
+    ) {
+        self.record(world, extent);
+        world.set_voxels_with(extent, f);
+    }
+
+    /// Records the current voxels of an extent that is about to be edited.
+    pub fn record(&mut self, world: &VoxelWorld, extent: Extent<IVec3>) {
+        if extent.shape.cmple(IVec3::ZERO).any() {
+            return;
+        }
+
+        let record = EditRecord::capture(world, extent);
+
+        // A new edit invalidates everything that was undone.
+        self.memory_used -= self.redo.drain(..).map(|t| t.size).sum::<usize>();
+
+        match &mut self.open {
+            Some(transaction) => {
+                self.memory_used += record.size();
+                transaction.push(record);
+                self.enforce_budget();
+            }
+            None => {
+                let mut transaction = EditTransaction::default();
+                transaction.push(record);
+                self.push_undo(transaction);
+            }
+        }
+    }
+
+    /// Reverts the most recent transaction, returning `false` if there is nothing to undo.
+    pub fn undo(&mut self, world: &mut VoxelWorld) -> bool {
+        self.commit();
+
+        let Some(transaction) = self.undo.pop_back() else {
+            return false;
+        };
+
+        self.memory_used -= transaction.size;
+        let inverse = transaction.apply(world);
+        self.memory_used += inverse.size;
+        self.redo.push(inverse);
+
+        self.enforce_budget();
+
+        true
+    }
+
+    /// Reapplies the most recently undone transaction, returning `false` if there is nothing to
+    /// redo.
+    pub fn redo(&mut self, world: &mut VoxelWorld) -> bool {
+        self.commit();
+
+        let Some(transaction) = self.redo.pop() else {
+            return false;
+        };
+
+        self.memory_used -= transaction.size;
+        let inverse = transaction.apply(world);
+        self.push_undo(inverse);
+
+        true
+    }
+
+    fn push_undo(&mut self, transaction: EditTransaction) {
+        if transaction.records.is_empty() {
+            return;
+        }
+
+        self.memory_used += transaction.size;
+        self.undo.push_back(transaction);
+        self.enforce_budget();
+    }
+
+    /// Discards the oldest transactions until the recorded voxels fit in the memory budget.
+    fn enforce_budget(&mut self) {
+        while self.memory_used > self.memory_budget {
+            if let Some(transaction) = self.undo.pop_front() {
+                self.memory_used -= transaction.size;
+            } else if !self.redo.is_empty() {
+                let transaction = self.redo.remove(0);
+                self.memory_used -= transaction.size;
+            } else {
+                break;
+            }
+        }
+    }
+}
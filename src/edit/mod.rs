@@ -0,0 +1,35 @@
+pub mod clipboard;
+pub mod history;
+
+use crate::prelude::*;
+
+use self::{clipboard::Clipboard, history::EditHistory};
+
+pub struct EditPlugin;
+
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditHistory>()
+            .init_resource::<Clipboard>()
+            .add_systems(Update, handle_undo_redo);
+    }
+}
+
+/// Undoes with Ctrl+Z and redoes with Ctrl+Y or Ctrl+Shift+Z.
+fn handle_undo_redo(
+    keys: Res<Input<KeyCode>>,
+    mut history: ResMut<EditHistory>,
+    mut world: ResMut<VoxelWorld>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if keys.just_pressed(KeyCode::Z) && !shift {
+        history.undo(&mut world);
+    } else if keys.just_pressed(KeyCode::Y) || (keys.just_pressed(KeyCode::Z) && shift) {
+        history.redo(&mut world);
+    }
+}
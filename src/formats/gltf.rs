@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use ilattice::prelude::Extent;
+
+use crate::{prelude::*, render::mesh::chunk::mesh_chunk};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: &[u8; 4] = b"JSON";
+const CHUNK_BIN: &[u8; 4] = b"BIN\0";
+
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const COMPONENT_FLOAT: u32 = 5126;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+
+/// The visible faces of a world extent, with positions relative to its minimum corner.
+#[derive(Clone, Debug, Default)]
+pub struct ExtentMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Vertex colors in sRGB space.
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExtentMesh {
+    /// Meshes a world extent with the same mesher used for rendering. The extent is meshed in
+    /// isolation, so faces on its boundary are kept. Voxels in chunks that are not loaded are
+    /// left empty.
+    pub fn new(registry: &BlockRegistry, world: &VoxelWorld, extent: Extent<IVec3>) -> Self {
+        let mut mesh = Self::default();
+        let size = CHUNK_SIZE as i32;
+
+        for z in (0..extent.shape.z).step_by(CHUNK_SIZE as usize) {
+            for y in (0..extent.shape.y).step_by(CHUNK_SIZE as usize) {
+                for x in (0..extent.shape.x).step_by(CHUNK_SIZE as usize) {
+                    let offset = IVec3::new(x, y, z);
+                    let tile = Extent::from_min_and_shape(
+                        extent.minimum + offset,
+                        (extent.shape - offset).min(IVec3::splat(size)),
+                    );
+
+                    // Pad the tile with its neighbours so that no faces are meshed between
+                    // tiles, but leave the padding outside the extent empty so that faces facing
+                    // out of it are meshed.
+                    let minimum = (tile.minimum - IVec3::ONE).max(extent.minimum);
+                    let least_upper_bound =
+                        (tile.least_upper_bound() + IVec3::ONE).min(extent.least_upper_bound());
+                    let padded = Extent::from_min_and_lub(minimum, least_upper_bound);
+                    let offset_in_chunk = (minimum - tile.minimum + IVec3::ONE).as_uvec3();
+
+                    let mut chunk = VoxelChunk::default();
+                    for (position, voxel) in world.get_voxels(padded).iter() {
+                        if voxel != Voxel::EMPTY {
+                            chunk.voxels.set_voxel_at(position + offset_in_chunk, voxel);
+                        }
+                    }
+
+                    let data = mesh_chunk(&chunk, registry);
+                    let origin = (offset - IVec3::ONE).as_vec3();
+                    let base = mesh.positions.len() as u32;
+
+                    mesh.positions.extend(
+                        data.positions
+                            .iter()
+                            .map(|position| (Vec3::from(*position) + origin).to_array()),
+                    );
+                    mesh.normals.extend_from_slice(&data.normals);
+                    mesh.colors.extend_from_slice(&data.colors);
+                    mesh.indices
+                        .extend(data.indices.iter().map(|index| index + base));
+                }
+            }
+        }
+
+        mesh
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Writes the mesh as a binary glTF (`.glb`) file with a single node and vertex colors.
+    pub fn save_glb(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_glb(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_glb(&self, writer: &mut impl Write) -> io::Result<()> {
+        let (json, mut bin) = if self.is_empty() {
+            (
+                r#"{"asset":{"version":"2.0","generator":"voxel"},"scene":0,"scenes":[{"nodes":[]}]}"#
+                    .to_owned(),
+                Vec::new(),
+            )
+        } else {
+            self.to_gltf()
+        };
+
+        let mut json = json.into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let mut length = 12 + 8 + json.len();
+        if !bin.is_empty() {
+            length += 8 + bin.len();
+        }
+        let length = u32::try_from(length)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "mesh is too large"))?;
+
+        writer.write_all(GLB_MAGIC)?;
+        writer.write_all(&GLB_VERSION.to_le_bytes())?;
+        writer.write_all(&length.to_le_bytes())?;
+
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(CHUNK_JSON)?;
+        writer.write_all(&json)?;
+
+        if !bin.is_empty() {
+            writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+            writer.write_all(CHUNK_BIN)?;
+            writer.write_all(&bin)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the glTF JSON document and its binary buffer.
+    fn to_gltf(&self) -> (String, Vec<u8>) {
+        let mut bin = Vec::new();
+
+        let mut minimum = Vec3::splat(f32::INFINITY);
+        let mut maximum = Vec3::splat(f32::NEG_INFINITY);
+        for position in &self.positions {
+            minimum = minimum.min(Vec3::from(*position));
+            maximum = maximum.max(Vec3::from(*position));
+            bin.extend(position.iter().flat_map(|value| value.to_le_bytes()));
+        }
+
+        let normals_offset = bin.len();
+        for normal in &self.normals {
+            bin.extend(normal.iter().flat_map(|value| value.to_le_bytes()));
+        }
+
+        // glTF vertex colors are linear.
+        let colors_offset = bin.len();
+        for [r, g, b, a] in &self.colors {
+            let color = Color::rgba(*r, *g, *b, *a).as_linear_rgba_f32();
+            bin.extend(color.iter().flat_map(|value| value.to_le_bytes()));
+        }
+
+        let indices_offset = bin.len();
+        for index in &self.indices {
+            bin.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let vertices = self.positions.len();
+        let buffer_view = |offset: usize, length: usize, target: u32| {
+            format!(
+                r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length},"target":{target}}}"#
+            )
+        };
+
+        let json = format!(
+            concat!(
+                r#"{{"asset":{{"version":"2.0","generator":"voxel"}},"#,
+                r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+                r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3,"mode":4}}]}}],"#,
+                r#""accessors":["#,
+                r#"{{"bufferView":0,"componentType":{float},"count":{vertices},"type":"VEC3","min":[{min_x},{min_y},{min_z}],"max":[{max_x},{max_y},{max_z}]}},"#,
+                r#"{{"bufferView":1,"componentType":{float},"count":{vertices},"type":"VEC3"}},"#,
+                r#"{{"bufferView":2,"componentType":{float},"count":{vertices},"type":"VEC4"}},"#,
+                r#"{{"bufferView":3,"componentType":{uint},"count":{indices},"type":"SCALAR"}}"#,
+                r#"],"bufferViews":[{positions},{normals},{colors},{index_view}],"#,
+                r#""buffers":[{{"byteLength":{length}}}]}}"#,
+            ),
+            float = COMPONENT_FLOAT,
+            uint = COMPONENT_UNSIGNED_INT,
+            vertices = vertices,
+            indices = self.indices.len(),
+            min_x = minimum.x,
+            min_y = minimum.y,
+            min_z = minimum.z,
+            max_x = maximum.x,
+            max_y = maximum.y,
+            max_z = maximum.z,
+            positions = buffer_view(0, normals_offset, TARGET_ARRAY_BUFFER),
+            normals = buffer_view(
+                normals_offset,
+                colors_offset - normals_offset,
+                TARGET_ARRAY_BUFFER
+            ),
+            colors = buffer_view(
+                colors_offset,
+                indices_offset - colors_offset,
+                TARGET_ARRAY_BUFFER
+            ),
+            index_view = buffer_view(
+                indices_offset,
+                bin.len() - indices_offset,
+                TARGET_ELEMENT_ARRAY_BUFFER
+            ),
+            length = bin.len(),
+        );
+
+        (json, bin)
+    }
+}
+
+/// Meshes the voxels within a world extent and writes them to a binary glTF (`.glb`) file with
+/// vertex colors. The extent's minimum corner is placed at the origin.
+pub fn export_glb(
+    path: impl AsRef<Path>,
+    registry: &BlockRegistry,
+    world: &VoxelWorld,
+    extent: Extent<IVec3>,
+) -> io::Result<()> {
+    ExtentMesh::new(registry, world, extent).save_glb(path)
+}
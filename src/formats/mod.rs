@@ -0,0 +1,11 @@
+use std::io;
+
+pub mod gltf;
+pub mod nbt;
+pub mod schematic;
+pub mod stl;
+pub mod vox;
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,369 @@
+use std::io::{self, Read};
+
+use bevy::utils::HashMap;
+use flate2::read::GzDecoder;
+
+use super::invalid_data;
+
+/// The deepest nesting of lists and compounds accepted, guarding against malicious files.
+const MAX_DEPTH: usize = 512;
+
+/// A value in a Named Binary Tag tree, as used by Minecraft.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Returns a child of a compound tag.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Self::Compound(compound) => compound.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any integer tag.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::Byte(value) => Some(value as i64),
+            Self::Short(value) => Some(value as i64),
+            Self::Int(value) => Some(value as i64),
+            Self::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Self::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Self::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Self::ByteArray(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Returns the values of an int array tag, or of a list of integer tags.
+    pub fn to_int_vec(&self) -> Option<Vec<i32>> {
+        match self {
+            Self::IntArray(array) => Some(array.clone()),
+            Self::List(list) => list
+                .iter()
+                .map(|tag| tag.as_i64().map(|value| value as i32))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the root tag of an NBT document, returning its name and value. Gzip-compressed
+/// documents are decompressed transparently.
+pub fn read_nbt(bytes: &[u8]) -> io::Result<(String, Tag)> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+        return read_nbt_uncompressed(&mut &decompressed[..]);
+    }
+
+    read_nbt_uncompressed(&mut &bytes[..])
+}
+
+fn read_nbt_uncompressed(reader: &mut &[u8]) -> io::Result<(String, Tag)> {
+    let id = read_u8(reader)?;
+    if id != 10 {
+        return Err(invalid_data("NBT root is not a compound"));
+    }
+
+    let name = read_string(reader)?;
+    let tag = read_payload(reader, id, 0)?;
+
+    Ok((name, tag))
+}
+
+fn read_payload(reader: &mut &[u8], id: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("NBT is nested too deeply"));
+    }
+
+    Ok(match id {
+        1 => Tag::Byte(read_u8(reader)? as i8),
+        2 => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        3 => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+        4 => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        5 => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        6 => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        7 => {
+            let len = read_len(reader, 1)?;
+            let (bytes, rest) = reader.split_at(len);
+            *reader = rest;
+            Tag::ByteArray(bytes.iter().map(|byte| *byte as i8).collect())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element_id = read_u8(reader)?;
+            let len = read_len(reader, 1)?;
+            let mut list = Vec::with_capacity(len);
+
+            for _ in 0..len {
+                list.push(read_payload(reader, element_id, depth + 1)?);
+            }
+
+            Tag::List(list)
+        }
+        10 => {
+            let mut compound = HashMap::new();
+
+            loop {
+                let child_id = read_u8(reader)?;
+                if child_id == 0 {
+                    break;
+                }
+
+                let name = read_string(reader)?;
+                compound.insert(name, read_payload(reader, child_id, depth + 1)?);
+            }
+
+            Tag::Compound(compound)
+        }
+        11 => {
+            let len = read_len(reader, 4)?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(i32::from_be_bytes(read_array(reader)?));
+            }
+            Tag::IntArray(array)
+        }
+        12 => {
+            let len = read_len(reader, 8)?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(i64::from_be_bytes(read_array(reader)?));
+            }
+            Tag::LongArray(array)
+        }
+        _ => return Err(invalid_data(format!("unknown NBT tag {id}"))),
+    })
+}
+
+fn read_u8(reader: &mut &[u8]) -> io::Result<u8> {
+    Ok(read_array::<1>(reader)?[0])
+}
+
+fn read_array<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads an array length, checking that the remaining input can hold that many elements of
+/// `element_size` bytes so that corrupt lengths fail early instead of allocating.
+fn read_len(reader: &mut &[u8], element_size: usize) -> io::Result<usize> {
+    let len = i32::from_be_bytes(read_array(reader)?).max(0) as usize;
+
+    if len.saturating_mul(element_size) > reader.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated NBT array",
+        ));
+    }
+
+    Ok(len)
+}
+
+/// Reads a string. NBT strings are modified UTF-8, which only differs from UTF-8 for characters
+/// that do not occur in block names, so invalid sequences are replaced rather than rejected.
+fn read_string(reader: &mut &[u8]) -> io::Result<String> {
+    let len = u16::from_be_bytes(read_array(reader)?) as usize;
+
+    if len > reader.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated NBT string",
+        ));
+    }
+
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    /// Encodes an uncompressed NBT document.
+    pub(crate) fn write_nbt(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut out = vec![id(tag)];
+        write_string(&mut out, name);
+        write_payload(&mut out, tag);
+        out
+    }
+
+    fn id(tag: &Tag) -> u8 {
+        match tag {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn write_string(out: &mut Vec<u8>, value: &str) {
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+        match tag {
+            Tag::Byte(value) => out.push(*value as u8),
+            Tag::Short(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                out.extend(values.iter().map(|value| *value as u8));
+            }
+            Tag::String(value) => write_string(out, value),
+            Tag::List(values) => {
+                out.push(values.first().map_or(0, id));
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                for value in values {
+                    write_payload(out, value);
+                }
+            }
+            Tag::Compound(compound) => {
+                for (name, value) in compound {
+                    out.push(id(value));
+                    write_string(out, name);
+                    write_payload(out, value);
+                }
+                out.push(0);
+            }
+            Tag::IntArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                for value in values {
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Tag::LongArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                for value in values {
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Tag {
+        Tag::Compound(
+            entries
+                .into_iter()
+                .map(|(name, tag)| (name.to_owned(), tag))
+                .collect(),
+        )
+    }
+
+    fn every_tag() -> Tag {
+        compound([
+            ("byte", Tag::Byte(-3)),
+            ("short", Tag::Short(-300)),
+            ("int", Tag::Int(70000)),
+            ("long", Tag::Long(-1 << 40)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-2.25)),
+            ("bytes", Tag::ByteArray(vec![1, -1, 127])),
+            ("string", Tag::String("minecraft:stone".into())),
+            ("list", Tag::List(vec![Tag::Short(1), Tag::Short(2)])),
+            ("empty", Tag::List(Vec::new())),
+            ("nested", compound([("inner", Tag::Int(1))])),
+            ("ints", Tag::IntArray(vec![1, -2, 3])),
+            ("longs", Tag::LongArray(vec![i64::MIN, i64::MAX])),
+        ])
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = write_nbt("root", &every_tag());
+        assert_eq!(read_nbt(&bytes).unwrap(), ("root".into(), every_tag()));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&bytes).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(read_nbt(&compressed).unwrap(), ("root".into(), every_tag()));
+    }
+
+    #[test]
+    fn truncated_documents() {
+        let bytes = write_nbt("root", &every_tag());
+
+        for len in 0..bytes.len() {
+            assert!(read_nbt(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupt_documents() {
+        // The root must be a compound.
+        assert!(read_nbt(&write_nbt("", &Tag::Int(1))).is_err());
+
+        // An array claiming far more elements than the document holds.
+        let mut bytes = write_nbt("", &compound([("ints", Tag::IntArray(vec![1]))]));
+        let len = bytes.len() - 9;
+        bytes[len..len + 4].copy_from_slice(&i32::MAX.to_be_bytes());
+        let err = read_nbt(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Lists nested deeper than the reader accepts.
+        let mut tag = Tag::List(Vec::new());
+        for _ in 0..MAX_DEPTH + 1 {
+            tag = Tag::List(vec![tag]);
+        }
+        let err = read_nbt(&write_nbt("", &compound([("deep", tag)]))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use bevy::utils::HashMap;
+use ilattice::prelude::Extent;
+
+use crate::{edit::history::EditHistory, prelude::*};
+
+use super::{
+    invalid_data,
+    nbt::{read_nbt, Tag},
+};
+
+/// Block names that are always imported as empty space.
+const AIR_BLOCKS: &[&str] = &[
+    "minecraft:air",
+    "minecraft:cave_air",
+    "minecraft:void_air",
+    "minecraft:structure_void",
+];
+
+/// A block structure loaded from a Sponge schematic (`.schem`) or a Minecraft structure file
+/// (`.nbt`).
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    pub size: UVec3,
+    /// Block states such as `minecraft:oak_stairs[facing=east,half=bottom]`.
+    pub palette: Vec<String>,
+    /// Palette indices laid out like a [`VoxelVolume`], with x varying fastest, then y, then z.
+    pub blocks: Vec<u32>,
+}
+
+/// Maps Minecraft block names to registered blocks.
+#[derive(Clone, Debug, Default)]
+pub struct BlockMapping {
+    blocks: HashMap<String, Voxel>,
+    /// Whether air in the schematic clears the world. Otherwise air leaves the world untouched.
+    pub include_air: bool,
+}
+
+impl BlockMapping {
+    /// Adds a mapping. `name` is either a bare block name such as `minecraft:stone`, or a full
+    /// block state such as `minecraft:oak_log[axis=y]`, which takes precedence.
+    pub fn insert(&mut self, name: impl Into<String>, voxel: Voxel) {
+        self.blocks.insert(name.into(), voxel);
+    }
+
+    /// Loads a RON map from Minecraft block names to registered block names.
+    pub fn load(path: impl AsRef<Path>, registry: &BlockRegistry) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let names: HashMap<String, String> = ron::from_str(&source)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let mut mapping = Self::default();
+        for (name, block) in names {
+            let voxel = registry.id(&block).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{name:?} is mapped to unknown block {block:?}"),
+                )
+            })?;
+            mapping.insert(name, voxel);
+        }
+
+        Ok(mapping)
+    }
+
+    /// Returns the voxel for a block state, or `None` if it is not mapped.
+    ///
+    /// Properties of the block state that match a state property of the registered block are
+    /// carried over, so `facing=east` sets `facing` to `east` if the block declares it.
+    pub fn resolve(&self, registry: &BlockRegistry, state: &str) -> Option<Voxel> {
+        let (name, properties) = match state.split_once('[') {
+            Some((name, properties)) => (name, properties.trim_end_matches(']')),
+            None => (state, ""),
+        };
+
+        if AIR_BLOCKS.contains(&name) {
+            return Some(Voxel::EMPTY);
+        }
+
+        if let Some(voxel) = self.blocks.get(state) {
+            return Some(*voxel);
+        }
+
+        let mut voxel = *self.blocks.get(name)?;
+        let definition = registry.get(voxel);
+
+        for property in properties.split(',') {
+            if let Some((key, value)) = property.split_once('=') {
+                if let Some(with_state) = definition.with_state_value(voxel, key, value) {
+                    voxel = with_state;
+                }
+            }
+        }
+
+        Some(voxel)
+    }
+}
+
+/// The result of importing a schematic into the world.
+#[derive(Clone, Debug)]
+pub struct SchematicImport {
+    pub extent: Extent<IVec3>,
+    /// The block states that have no mapping, with the number of blocks of each. These blocks
+    /// are left empty.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl Schematic {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let (_, root) = read_nbt(bytes)?;
+
+        // Sponge schematics version 3 wrap everything in a `Schematic` compound.
+        let root = root.get("Schematic").unwrap_or(&root);
+
+        if root.get("BlockData").is_some() || root.get("Blocks").is_some() {
+            Self::parse_sponge(root)
+        } else if root.get("size").is_some() && root.get("blocks").is_some() {
+            Self::parse_structure(root)
+        } else {
+            Err(invalid_data("unsupported schematic format"))
+        }
+    }
+
+    fn parse_sponge(root: &Tag) -> io::Result<Self> {
+        let dimension = |key: &str| {
+            root.get(key)
+                .and_then(Tag::as_i64)
+                .map(|value| value as u16 as u32)
+                .ok_or_else(|| invalid_data(format!("schematic is missing {key}")))
+        };
+        let size = UVec3::new(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+
+        // Version 3 moves the palette and data into a `Blocks` compound and renames the data.
+        let (palette_tag, data) = match root.get("Blocks") {
+            Some(blocks) => (blocks.get("Palette"), blocks.get("Data")),
+            None => (root.get("Palette"), root.get("BlockData")),
+        };
+
+        let palette_tag = palette_tag
+            .and_then(Tag::as_compound)
+            .ok_or_else(|| invalid_data("schematic is missing its palette"))?;
+        let data = data
+            .and_then(Tag::as_byte_array)
+            .ok_or_else(|| invalid_data("schematic is missing its block data"))?;
+
+        let mut palette = vec![String::new(); palette_tag.len()];
+        for (name, index) in palette_tag {
+            let index = index
+                .as_i64()
+                .filter(|index| (0..palette.len() as i64).contains(index))
+                .ok_or_else(|| invalid_data(format!("invalid palette index for {name}")))?;
+            palette[index as usize] = name.clone();
+        }
+
+        let volume = VoxelVolume::checked_len(size)
+            .ok_or_else(|| invalid_data("schematic is too large"))? as usize;
+
+        // Every block takes at least one byte.
+        if data.len() < volume {
+            return Err(invalid_data("schematic block data is truncated"));
+        }
+
+        let mut blocks = vec![0; volume];
+        let mut bytes = data.iter().map(|byte| *byte as u8);
+
+        // Blocks are stored as varints with x varying fastest, then z, then y.
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    let index = read_varint(&mut bytes)?;
+                    if index as usize >= palette.len() {
+                        return Err(invalid_data(
+                            "block data references a missing palette entry",
+                        ));
+                    }
+
+                    blocks[(x + size.x * (y + size.y * z)) as usize] = index;
+                }
+            }
+        }
+
+        Ok(Self {
+            size,
+            palette,
+            blocks,
+        })
+    }
+
+    fn parse_structure(root: &Tag) -> io::Result<Self> {
+        let size = root
+            .get("size")
+            .and_then(Tag::to_int_vec)
+            .filter(|size| size.len() == 3 && size.iter().all(|value| *value >= 0))
+            .map(|size| UVec3::new(size[0] as u32, size[1] as u32, size[2] as u32))
+            .ok_or_else(|| invalid_data("structure has an invalid size"))?;
+
+        // Structures with several palettes pick one at random when placed; use the first.
+        let palette_tag = root
+            .get("palette")
+            .or_else(|| root.get("palettes").and_then(|p| p.as_list()?.first()))
+            .and_then(Tag::as_list)
+            .ok_or_else(|| invalid_data("structure is missing its palette"))?;
+
+        let mut palette = palette_tag
+            .iter()
+            .map(|state| {
+                let name = state
+                    .get("Name")
+                    .and_then(Tag::as_str)
+                    .ok_or_else(|| invalid_data("palette entry has no name"))?;
+
+                let Some(properties) = state.get("Properties").and_then(Tag::as_compound) else {
+                    return Ok(name.to_owned());
+                };
+
+                let mut properties = properties
+                    .iter()
+                    .filter_map(|(key, value)| Some(format!("{key}={}", value.as_str()?)))
+                    .collect::<Vec<_>>();
+                properties.sort();
+
+                Ok(format!("{name}[{}]", properties.join(",")))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Positions not listed in a structure are structure voids.
+        let void = palette.len() as u32;
+        palette.push("minecraft:structure_void".into());
+
+        let volume = VoxelVolume::checked_len(size)
+            .ok_or_else(|| invalid_data("structure is too large"))? as usize;
+
+        let block_tags = root
+            .get("blocks")
+            .and_then(Tag::as_list)
+            .ok_or_else(|| invalid_data("structure is missing its blocks"))?;
+
+        // Each position is listed at most once.
+        if block_tags.len() > volume {
+            return Err(invalid_data(
+                "structure has more blocks than fit in its size",
+            ));
+        }
+
+        let mut blocks = vec![void; volume];
+
+        for block in block_tags {
+            let position = block
+                .get("pos")
+                .and_then(Tag::to_int_vec)
+                .filter(|pos| {
+                    pos.len() == 3
+                        && pos.iter().all(|value| *value >= 0)
+                        && UVec3::new(pos[0] as u32, pos[1] as u32, pos[2] as u32)
+                            .cmplt(size)
+                            .all()
+                })
+                .ok_or_else(|| invalid_data("structure block has an invalid position"))?;
+            let state = block
+                .get("state")
+                .and_then(Tag::as_i64)
+                .filter(|state| (0..void as i64).contains(state))
+                .ok_or_else(|| invalid_data("structure block has an invalid state"))?;
+
+            let [x, y, z] = [position[0] as u32, position[1] as u32, position[2] as u32];
+            blocks[(x + size.x * (y + size.y * z)) as usize] = state as u32;
+        }
+
+        Ok(Self {
+            size,
+            palette,
+            blocks,
+        })
+    }
+
+    /// Converts the schematic into a volume, returning the block states without a mapping along
+    /// with their counts.
+    pub fn to_volume(
+        &self,
+        registry: &BlockRegistry,
+        mapping: &BlockMapping,
+    ) -> (VoxelVolume, BTreeMap<String, usize>) {
+        let voxels: Vec<_> = self
+            .palette
+            .iter()
+            .map(|state| mapping.resolve(registry, state))
+            .collect();
+
+        let mut volume = VoxelVolume::new(self.size);
+        let mut unmapped = BTreeMap::new();
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            match voxels[*block as usize] {
+                Some(voxel) => {
+                    let position = volume.delinearize(index);
+                    *volume.voxel_at_mut(position) = voxel;
+                }
+                None => {
+                    *unmapped
+                        .entry(self.palette[*block as usize].clone())
+                        .or_default() += 1
+                }
+            }
+        }
+
+        (volume, unmapped)
+    }
+}
+
+/// Loads a schematic and pastes it into the world with its minimum corner at `minimum`,
+/// recording the edit in `history`.
+pub fn import_schematic(
+    path: impl AsRef<Path>,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+    history: &mut EditHistory,
+    world: &mut VoxelWorld,
+    minimum: WorldVoxelPos,
+) -> io::Result<SchematicImport> {
+    let (volume, unmapped) = Schematic::load(path)?.to_volume(registry, mapping);
+
+    if mapping.include_air {
+        history.set_voxels(world, minimum, &volume);
+    } else {
+        history.merge_voxels(world, minimum, &volume);
+    }
+
+    Ok(SchematicImport {
+        extent: Extent::from_min_and_shape(minimum.0, volume.shape().as_ivec3()),
+        unmapped,
+    })
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> io::Result<u32> {
+    let mut value = 0;
+
+    for shift in (0..35).step_by(7) {
+        let byte = bytes
+            .next()
+            .ok_or_else(|| invalid_data("block data is truncated"))?;
+        value |= ((byte & 0x7f) as u32) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid_data("block data contains an invalid varint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::nbt::tests::{compound, write_nbt};
+
+    fn sponge(size: [i32; 3], palette: &[&str], data: Vec<i8>) -> Vec<u8> {
+        let palette = palette
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.to_string(), Tag::Int(index as i32)))
+            .collect();
+
+        write_nbt(
+            "Schematic",
+            &compound([
+                ("Width", Tag::Short(size[0] as i16)),
+                ("Height", Tag::Short(size[1] as i16)),
+                ("Length", Tag::Short(size[2] as i16)),
+                ("Palette", Tag::Compound(palette)),
+                ("BlockData", Tag::ByteArray(data)),
+            ]),
+        )
+    }
+
+    #[test]
+    fn parse_sponge() {
+        let mut palette: Vec<String> = (0..131).map(|index| format!("test:{index}")).collect();
+        palette[1] = "minecraft:stone".into();
+        let palette: Vec<&str> = palette.iter().map(String::as_str).collect();
+
+        // x varies fastest, then z, then y. Index 130 takes two varint bytes.
+        let bytes = sponge([2, 2, 1], &palette, vec![0, 1, 0x82u8 as i8, 0x01, 0]);
+        let schematic = Schematic::parse(&bytes).unwrap();
+
+        assert_eq!(schematic.size, UVec3::new(2, 2, 1));
+        assert_eq!(schematic.palette[1], "minecraft:stone");
+        assert_eq!(schematic.blocks, [0, 1, 130, 0]);
+    }
+
+    #[test]
+    fn parse_structure() {
+        let bytes = write_nbt(
+            "",
+            &compound([
+                (
+                    "size",
+                    Tag::List(vec![Tag::Int(2), Tag::Int(1), Tag::Int(1)]),
+                ),
+                (
+                    "palette",
+                    Tag::List(vec![
+                        compound([("Name", Tag::String("minecraft:stone".into()))]),
+                        compound([
+                            ("Name", Tag::String("minecraft:oak_log".into())),
+                            ("Properties", compound([("axis", Tag::String("y".into()))])),
+                        ]),
+                    ]),
+                ),
+                (
+                    "blocks",
+                    Tag::List(vec![compound([
+                        (
+                            "pos",
+                            Tag::List(vec![Tag::Int(1), Tag::Int(0), Tag::Int(0)]),
+                        ),
+                        ("state", Tag::Int(1)),
+                    ])]),
+                ),
+            ]),
+        );
+        let schematic = Schematic::parse(&bytes).unwrap();
+
+        assert_eq!(schematic.palette[1], "minecraft:oak_log[axis=y]");
+        assert_eq!(schematic.palette[2], "minecraft:structure_void");
+        assert_eq!(schematic.blocks, [2, 1]);
+    }
+
+    #[test]
+    fn corrupt_sponge() {
+        let palette = ["minecraft:air", "minecraft:stone"];
+
+        for bytes in [
+            // Fewer blocks than the size holds.
+            sponge([2, 2, 1], &palette, vec![0, 1, 0]),
+            // A varint running past the end of the data.
+            sponge([1, 1, 1], &palette, vec![0x80u8 as i8]),
+            // A missing palette entry.
+            sponge([1, 1, 1], &palette, vec![2]),
+            // Far more blocks than the data could ever hold.
+            sponge([-1, -1, -1], &palette, vec![0]),
+        ] {
+            let err = Schematic::parse(&bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn oversized_structure() {
+        let bytes = write_nbt(
+            "",
+            &compound([
+                ("size", Tag::IntArray(vec![i32::MAX, i32::MAX, i32::MAX])),
+                ("palette", Tag::List(Vec::new())),
+                ("blocks", Tag::List(Vec::new())),
+            ]),
+        );
+
+        let err = Schematic::parse(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
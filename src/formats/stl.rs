@@ -0,0 +1,418 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::utils::HashSet;
+use ilattice::prelude::Extent;
+
+use crate::prelude::*;
+
+/// How a world extent is turned into a printable solid.
+#[derive(Clone, Debug)]
+pub struct StlOptions {
+    /// The number of solid layers added below the extent.
+    pub base_thickness: u32,
+    /// The size of a voxel in the output, in millimetres.
+    pub voxel_size: f32,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        Self {
+            base_thickness: 2,
+            voxel_size: 1.0,
+        }
+    }
+}
+
+/// The opaque voxels of an extent, with an empty border one voxel wide on every side.
+struct SolidGrid {
+    shape: UVec3,
+    cells: Vec<bool>,
+}
+
+impl SolidGrid {
+    /// Builds the grid for an extent, adding the base below it and filling every column on the
+    /// sides of the extent up to its highest voxel so that the print has closed walls.
+    fn new(
+        registry: &BlockRegistry,
+        world: &VoxelWorld,
+        extent: Extent<IVec3>,
+        base_thickness: u32,
+    ) -> Self {
+        let volume = world.get_voxels(extent);
+        let size = volume.shape();
+        let base = if size.cmpgt(UVec3::ZERO).all() {
+            base_thickness
+        } else {
+            0
+        };
+
+        let mut grid = Self {
+            shape: size + UVec3::new(2, 2 + base, 2),
+            cells: Vec::new(),
+        };
+        grid.cells = vec![false; (grid.shape.x * grid.shape.y * grid.shape.z) as usize];
+
+        for (position, voxel) in volume.iter() {
+            if registry.get(voxel).visibility == BlockVisibility::Opaque {
+                grid.set(position + UVec3::new(1, 1 + base, 1));
+            }
+        }
+
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let wall = x == 0 || z == 0 || x == size.x - 1 || z == size.z - 1;
+                let top = if wall {
+                    (0..size.y)
+                        .rev()
+                        .find(|y| grid.get(UVec3::new(x + 1, y + 1 + base, z + 1)))
+                        .map_or(0, |y| y + 1)
+                } else {
+                    0
+                };
+
+                for y in 0..base + top {
+                    grid.set(UVec3::new(x + 1, y + 1, z + 1));
+                }
+            }
+        }
+
+        grid
+    }
+
+    #[inline]
+    fn index(&self, position: UVec3) -> usize {
+        (position.x + self.shape.x * (position.y + self.shape.y * position.z)) as usize
+    }
+
+    #[inline]
+    fn get(&self, position: UVec3) -> bool {
+        self.cells[self.index(position)]
+    }
+
+    #[inline]
+    fn set(&mut self, position: UVec3) {
+        let index = self.index(position);
+        self.cells[index] = true;
+    }
+
+    /// Fills voxels until the surface is a 2-manifold.
+    ///
+    /// The surface is manifold unless some lattice point has solid or empty voxels among its
+    /// eight neighbours that only touch along an edge or at the point itself. Each such point is
+    /// fixed by filling its neighbourhood, which may uncover others, so this repeats until none
+    /// remain. Only voxels inside the border are filled.
+    fn make_manifold(&mut self) {
+        let manifold: Vec<bool> = (0..=255).map(is_manifold_neighbourhood).collect();
+        let interior = self.shape - UVec3::ONE;
+
+        loop {
+            let mut changed = false;
+
+            for z in 0..self.shape.z - 1 {
+                for y in 0..self.shape.y - 1 {
+                    for x in 0..self.shape.x - 1 {
+                        let minimum = UVec3::new(x, y, z);
+                        let mask = (0..8).fold(0, |mask, corner| {
+                            let solid = self.get(minimum + corner_offset(corner));
+                            mask | ((solid as usize) << corner)
+                        });
+
+                        if manifold[mask] {
+                            continue;
+                        }
+
+                        for corner in 0..8 {
+                            let position = minimum + corner_offset(corner);
+                            if position.cmpge(UVec3::ONE).all() && position.cmplt(interior).all() {
+                                self.set(position);
+                            }
+                        }
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Merges the faces between solid and empty voxels into as few rectangles as possible.
+    fn greedy_quads(&self) -> Vec<StlQuad> {
+        let mut quads = Vec::new();
+
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (size_u, size_v) = (self.shape[u] as usize, self.shape[v] as usize);
+            let mut mask = vec![0i8; size_u * size_v];
+
+            for layer in 0..self.shape[axis] - 1 {
+                for j in 0..size_v {
+                    for i in 0..size_u {
+                        let mut position = UVec3::ZERO;
+                        position[axis] = layer;
+                        position[u] = i as u32;
+                        position[v] = j as u32;
+                        let below = self.get(position);
+                        position[axis] += 1;
+                        let above = self.get(position);
+
+                        mask[i + j * size_u] = below as i8 - above as i8;
+                    }
+                }
+
+                for j in 0..size_v {
+                    let mut i = 0;
+                    while i < size_u {
+                        let direction = mask[i + j * size_u];
+                        if direction == 0 {
+                            i += 1;
+                            continue;
+                        }
+
+                        let mut width = 1;
+                        while i + width < size_u && mask[i + width + j * size_u] == direction {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        while j + height < size_v
+                            && (i..i + width).all(|k| mask[k + (j + height) * size_u] == direction)
+                        {
+                            height += 1;
+                        }
+
+                        for row in j..j + height {
+                            mask[i + row * size_u..i + width + row * size_u].fill(0);
+                        }
+
+                        let mut minimum = IVec3::ZERO;
+                        minimum[axis] = layer as i32 + 1;
+                        minimum[u] = i as i32;
+                        minimum[v] = j as i32;
+
+                        quads.push(StlQuad {
+                            minimum,
+                            axis,
+                            positive: direction > 0,
+                            width: width as i32,
+                            height: height as i32,
+                        });
+
+                        i += width;
+                    }
+                }
+            }
+        }
+
+        quads
+    }
+}
+
+/// Returns the offset of one of the eight voxels around a lattice point, indexed by a bit per
+/// axis.
+#[inline]
+fn corner_offset(corner: u32) -> UVec3 {
+    UVec3::new(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1)
+}
+
+/// Returns whether the surface around a lattice point is a disk, given which of its eight
+/// neighbouring voxels are solid. This holds when the solid voxels and the empty voxels each
+/// form at most one face-connected group.
+fn is_manifold_neighbourhood(mask: usize) -> bool {
+    let groups = |set: usize| {
+        let mut remaining = set;
+        let mut count = 0;
+
+        while remaining != 0 {
+            let mut group = remaining & remaining.wrapping_neg();
+            loop {
+                let mut grown = group;
+                for axis in 0..3 {
+                    grown |= ((group & !axis_mask(axis)) << (1 << axis)) & set;
+                    grown |= ((group & axis_mask(axis)) >> (1 << axis)) & set;
+                }
+                if grown == group {
+                    break;
+                }
+                group = grown;
+            }
+
+            remaining &= !group;
+            count += 1;
+        }
+
+        count
+    };
+
+    groups(mask) <= 1 && groups(!mask & 0xff) <= 1
+}
+
+/// Returns the corners of the neighbourhood that are on the positive side along an axis.
+#[inline]
+fn axis_mask(axis: u32) -> usize {
+    (0..8)
+        .filter(|corner| (corner >> axis) & 1 == 1)
+        .fold(0, |mask, corner| mask | (1 << corner))
+}
+
+/// A face of the solid, in the lattice coordinates of a [`SolidGrid`].
+struct StlQuad {
+    minimum: IVec3,
+    axis: usize,
+    positive: bool,
+    width: i32,
+    height: i32,
+}
+
+impl StlQuad {
+    /// Returns the four corners, counter-clockwise when seen from outside the solid.
+    fn corners(&self) -> [IVec3; 4] {
+        let mut du = IVec3::ZERO;
+        du[(self.axis + 1) % 3] = self.width;
+        let mut dv = IVec3::ZERO;
+        dv[(self.axis + 2) % 3] = self.height;
+
+        let p = self.minimum;
+        if self.positive {
+            [p, p + du, p + du + dv, p + dv]
+        } else {
+            [p, p + dv, p + du + dv, p + du]
+        }
+    }
+
+    /// Returns the lattice points along the boundary that are corners of any quad, in the same
+    /// winding as [`Self::corners`].
+    fn boundary(&self, corners: &HashSet<IVec3>) -> Vec<IVec3> {
+        let quad = self.corners();
+        let mut boundary = Vec::with_capacity(4);
+
+        for (i, start) in quad.iter().enumerate() {
+            let end = quad[(i + 1) % 4];
+            let step = (end - *start).signum();
+            boundary.push(*start);
+
+            let mut point = *start + step;
+            while point != end {
+                if corners.contains(&point) {
+                    boundary.push(point);
+                }
+                point += step;
+            }
+        }
+
+        boundary
+    }
+
+    fn normal(&self) -> Vec3 {
+        let mut normal = Vec3::ZERO;
+        normal[self.axis] = if self.positive { 1.0 } else { -1.0 };
+        normal
+    }
+}
+
+/// A closed triangle mesh of the opaque voxels in a world extent, ready for 3D printing.
+pub struct StlMesh {
+    /// Each triangle's normal and vertices, in millimetres with z up.
+    pub triangles: Vec<[Vec3; 4]>,
+}
+
+impl StlMesh {
+    /// Meshes the opaque voxels of a world extent, ignoring translucent blocks such as water.
+    ///
+    /// The extent is closed with a base and side walls and filled where needed to make the
+    /// surface manifold. Faces are merged greedily; where a large face meets the corners of
+    /// smaller neighbours, it is fanned around its centre instead of split into two triangles,
+    /// so that no vertex lies on the edge of another triangle and the mesh stays watertight.
+    pub fn new(
+        registry: &BlockRegistry,
+        world: &VoxelWorld,
+        extent: Extent<IVec3>,
+        options: &StlOptions,
+    ) -> Self {
+        let mut grid = SolidGrid::new(registry, world, extent, options.base_thickness);
+        grid.make_manifold();
+
+        let quads = grid.greedy_quads();
+        let corners: HashSet<IVec3> = quads.iter().flat_map(StlQuad::corners).collect();
+
+        // The border of the grid is at -1, and the base is below the extent.
+        let origin = IVec3::new(1, 1 + options.base_thickness as i32, 1).as_vec3();
+        let to_output = |point: Vec3| {
+            let point = (point - origin) * options.voxel_size;
+            Vec3::new(point.x, -point.z, point.y)
+        };
+
+        let mut triangles = Vec::new();
+
+        for quad in &quads {
+            let normal = quad.normal();
+            let normal = Vec3::new(normal.x, -normal.z, normal.y);
+            let boundary: Vec<_> = quad
+                .boundary(&corners)
+                .into_iter()
+                .map(|point| to_output(point.as_vec3()))
+                .collect();
+
+            if let [a, b, c, d] = boundary[..] {
+                triangles.push([normal, a, b, c]);
+                triangles.push([normal, a, c, d]);
+            } else {
+                let [a, _, c, _] = quad.corners();
+                let center = to_output((a + c).as_vec3() / 2.0);
+                for (i, point) in boundary.iter().enumerate() {
+                    triangles.push([normal, center, *point, boundary[(i + 1) % boundary.len()]]);
+                }
+            }
+        }
+
+        Self { triangles }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the mesh as a binary STL.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let count = u32::try_from(self.triangles.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "mesh is too large"))?;
+
+        let mut header = [0; 80];
+        let name = b"voxel";
+        header[..name.len()].copy_from_slice(name);
+
+        writer.write_all(&header)?;
+        writer.write_all(&count.to_le_bytes())?;
+
+        for triangle in &self.triangles {
+            for vector in triangle {
+                for value in vector.to_array() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            writer.write_all(&0u16.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the opaque voxels within a world extent to a binary STL file for 3D printing. See
+/// [`StlMesh::new`].
+pub fn export_stl(
+    path: impl AsRef<Path>,
+    registry: &BlockRegistry,
+    world: &VoxelWorld,
+    extent: Extent<IVec3>,
+    options: &StlOptions,
+) -> io::Result<()> {
+    StlMesh::new(registry, world, extent, options).save(path)
+}
@@ -0,0 +1,916 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use bevy::utils::HashMap;
+use ilattice::prelude::Extent;
+
+use crate::{edit::history::EditHistory, prelude::*};
+
+use super::invalid_data;
+
+const MAGIC: &[u8; 4] = b"VOX ";
+const VERSION: i32 = 200;
+
+/// The largest model size the format allows along each axis.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+/// A model stored in a `.vox` file. Positions are in MagicaVoxel's z-up coordinates.
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: UVec3,
+    /// Each voxel's position and palette color index, which is never 0.
+    pub voxels: Vec<(UVec3, u8)>,
+}
+
+/// The farthest a single transform node may move its children along each axis. Scene graphs are
+/// at most [`MAX_SCENE_DEPTH`] deep, so scene positions always fit in an `i32`.
+const MAX_TRANSLATION: i32 = 1 << 20;
+
+/// A rotation matrix with a single `1` or `-1` in each row.
+pub type VoxRotation = [[i32; 3]; 3];
+
+pub const IDENTITY_ROTATION: VoxRotation = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// A placement of a model in the scene, with the transforms of all of its ancestors applied.
+#[derive(Clone, Copy, Debug)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub rotation: VoxRotation,
+    pub translation: IVec3,
+}
+
+/// The contents of a MagicaVoxel `.vox` file.
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    pub instances: Vec<VoxInstance>,
+    /// RGBA colors indexed by palette color index. Index 0 is unused.
+    pub palette: [[u8; 4]; 256],
+}
+
+/// How palette colors are turned into voxels when importing a `.vox` file.
+#[derive(Clone, Debug, Default)]
+pub struct VoxPaletteMapping {
+    /// Voxels to use for specific palette color indices. Colors without an entry are mapped to
+    /// the block with the nearest color in the [`BlockRegistry`].
+    pub colors: HashMap<u8, Voxel>,
+}
+
+impl VoxPaletteMapping {
+    fn resolve(&self, registry: &BlockRegistry, palette: &[[u8; 4]; 256]) -> [Voxel; 256] {
+        let mut voxels = [Voxel::EMPTY; 256];
+
+        for (index, voxel) in voxels.iter_mut().enumerate().skip(1) {
+            *voxel = match self.colors.get(&(index as u8)) {
+                Some(voxel) => *voxel,
+                None => {
+                    let [r, g, b, a] = palette[index];
+                    registry.nearest_color(Color::rgba_u8(r, g, b, a))
+                }
+            };
+        }
+
+        voxels
+    }
+}
+
+impl VoxFile {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = bytes;
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a .vox file"));
+        }
+        let _version = read_i32(&mut reader)?;
+
+        let main = read_chunk(&mut reader)?;
+        if &main.id != b"MAIN" {
+            return Err(invalid_data("missing MAIN chunk"));
+        }
+
+        let mut models = Vec::new();
+        let mut size = None;
+        let mut palette = default_palette();
+        let mut nodes = HashMap::new();
+
+        let mut children = main.children;
+        while !children.is_empty() {
+            let chunk = read_chunk(&mut children)?;
+            let mut content = chunk.content;
+
+            match &chunk.id {
+                b"SIZE" => {
+                    size = Some(UVec3::new(
+                        read_u32(&mut content)?,
+                        read_u32(&mut content)?,
+                        read_u32(&mut content)?,
+                    ));
+                }
+                b"XYZI" => {
+                    let size = size
+                        .take()
+                        .ok_or_else(|| invalid_data("XYZI chunk without SIZE chunk"))?;
+                    let count = read_u32(&mut content)? as usize;
+                    let mut voxels = Vec::with_capacity(count.min(content.len() / 4));
+
+                    for _ in 0..count {
+                        let mut voxel = [0; 4];
+                        content.read_exact(&mut voxel)?;
+                        let position =
+                            UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+
+                        if voxel[3] != 0 && position.cmplt(size).all() {
+                            voxels.push((position, voxel[3]));
+                        }
+                    }
+
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    for color in palette.iter_mut().skip(1) {
+                        content.read_exact(color)?;
+                    }
+                }
+                b"nTRN" => {
+                    let id = read_i32(&mut content)?;
+                    let attributes = read_dict(&mut content)?;
+                    let child = read_i32(&mut content)?;
+                    let _reserved = read_i32(&mut content)?;
+                    let _layer = read_i32(&mut content)?;
+                    let frames = read_i32(&mut content)?;
+
+                    let mut rotation = IDENTITY_ROTATION;
+                    let mut translation = IVec3::ZERO;
+
+                    // Only the first animation frame is imported.
+                    if frames > 0 {
+                        let frame = read_dict(&mut content)?;
+                        if let Some(r) = frame.get("_r") {
+                            rotation = parse_rotation(r)?;
+                        }
+                        if let Some(t) = frame.get("_t") {
+                            translation = parse_translation(t)?;
+                        }
+                    }
+
+                    nodes.insert(
+                        id,
+                        SceneNode::Transform {
+                            child,
+                            rotation,
+                            translation,
+                            hidden: attributes.get("_hidden").is_some_and(|h| h == "1"),
+                        },
+                    );
+                }
+                b"nGRP" => {
+                    let id = read_i32(&mut content)?;
+                    let _attributes = read_dict(&mut content)?;
+                    let count = read_i32(&mut content)?;
+                    let children = (0..count)
+                        .map(|_| read_i32(&mut content))
+                        .collect::<io::Result<_>>()?;
+
+                    nodes.insert(id, SceneNode::Group { children });
+                }
+                b"nSHP" => {
+                    let id = read_i32(&mut content)?;
+                    let _attributes = read_dict(&mut content)?;
+                    let count = read_i32(&mut content)?;
+                    let mut model = None;
+
+                    for _ in 0..count {
+                        let model_id = read_i32(&mut content)?;
+                        let _attributes = read_dict(&mut content)?;
+                        model.get_or_insert(model_id);
+                    }
+
+                    if let Some(model) = model {
+                        nodes.insert(id, SceneNode::Shape { model });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+
+        if nodes.is_empty() {
+            // Files without a scene graph place every model at the origin.
+            instances.extend((0..models.len()).map(|model| VoxInstance {
+                model,
+                rotation: IDENTITY_ROTATION,
+                translation: IVec3::ZERO,
+            }));
+        } else {
+            collect_instances(
+                &nodes,
+                0,
+                IDENTITY_ROTATION,
+                IVec3::ZERO,
+                &mut instances,
+                0,
+                &mut 0,
+            )?;
+        }
+
+        for instance in &instances {
+            if instance.model >= models.len() {
+                return Err(invalid_data("shape node references a missing model"));
+            }
+        }
+
+        Ok(Self {
+            models,
+            instances,
+            palette,
+        })
+    }
+
+    /// Returns the position of every voxel in the scene along with its palette color index,
+    /// converted from MagicaVoxel's z-up coordinates to the world's y-up coordinates.
+    pub fn scene_voxels(&self) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        self.instances.iter().flat_map(move |instance| {
+            let model = &self.models[instance.model];
+            let center = (model.size / 2).as_ivec3();
+
+            model.voxels.iter().map(move |(position, color)| {
+                let position =
+                    rotate(&instance.rotation, position.as_ivec3() - center) + instance.translation;
+
+                (IVec3::new(position.x, position.z, -position.y), *color)
+            })
+        })
+    }
+
+    /// Builds a volume containing the whole scene, mapping palette colors to voxels with
+    /// `mapping`. Returns `None` if the scene is empty, and fails if its bounding box holds more
+    /// than [`MAX_LOADED_VOLUME`] voxels.
+    pub fn to_volume(
+        &self,
+        registry: &BlockRegistry,
+        mapping: &VoxPaletteMapping,
+    ) -> io::Result<Option<VoxelVolume>> {
+        let voxels = mapping.resolve(registry, &self.palette);
+
+        let mut minimum = IVec3::MAX;
+        let mut maximum = IVec3::MIN;
+        for (position, _) in self.scene_voxels() {
+            minimum = minimum.min(position);
+            maximum = maximum.max(position);
+        }
+
+        if minimum.cmpgt(maximum).any() {
+            return Ok(None);
+        }
+
+        let shape = (maximum - minimum).as_uvec3() + UVec3::ONE;
+        if VoxelVolume::checked_len(shape).is_none() {
+            return Err(invalid_data(format!(
+                "scene of {}x{}x{} voxels is too large",
+                shape.x, shape.y, shape.z
+            )));
+        }
+
+        let mut volume = VoxelVolume::new(shape);
+        for (position, color) in self.scene_voxels() {
+            *volume.voxel_at_mut((position - minimum).as_uvec3()) = voxels[color as usize];
+        }
+
+        Ok(Some(volume))
+    }
+
+    /// Builds a scene from a volume, splitting it into models of at most [`MAX_MODEL_SIZE`] along
+    /// each axis. The palette is derived from the block colors in the [`BlockRegistry`]; if there
+    /// are more than 255 distinct colors, the remaining ones use the nearest palette color.
+    pub fn from_volume(volume: &VoxelVolume, registry: &BlockRegistry) -> Self {
+        let mut palette = [[0; 4]; 256];
+        let mut palette_len = 1;
+        let mut colors = HashMap::new();
+
+        let mut color_index = |voxel: Voxel| -> u8 {
+            let voxel = voxel.with_state(0);
+            if let Some(index) = colors.get(&voxel.0) {
+                return *index;
+            }
+
+            let color = registry.get(voxel).color.as_rgba_u8();
+            let index = match palette[1..palette_len].iter().position(|c| *c == color) {
+                Some(index) => index + 1,
+                None if palette_len < 256 => {
+                    palette[palette_len] = color;
+                    palette_len += 1;
+                    palette_len - 1
+                }
+                None => nearest_palette_color(&palette, color),
+            };
+
+            colors.insert(voxel.0, index as u8);
+            index as u8
+        };
+
+        let mut models = Vec::new();
+        let mut instances = Vec::new();
+        let tiles = (volume.shape() + UVec3::splat(MAX_MODEL_SIZE - 1)) / MAX_MODEL_SIZE;
+
+        for tz in 0..tiles.z {
+            for ty in 0..tiles.y {
+                for tx in 0..tiles.x {
+                    let tile_minimum = UVec3::new(tx, ty, tz) * MAX_MODEL_SIZE;
+                    let tile_shape =
+                        (volume.shape() - tile_minimum).min(UVec3::splat(MAX_MODEL_SIZE));
+                    // The model's z axis is the world's y axis, and its y axis the world's
+                    // negative z axis.
+                    let size = UVec3::new(tile_shape.x, tile_shape.z, tile_shape.y);
+
+                    let mut voxels = Vec::new();
+                    for z in 0..tile_shape.z {
+                        for y in 0..tile_shape.y {
+                            for x in 0..tile_shape.x {
+                                let voxel = volume.voxel_at(tile_minimum + UVec3::new(x, y, z));
+                                if voxel != Voxel::EMPTY {
+                                    voxels.push((
+                                        UVec3::new(x, tile_shape.z - 1 - z, y),
+                                        color_index(voxel),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
+                    if voxels.is_empty() {
+                        continue;
+                    }
+
+                    let minimum = tile_minimum.as_ivec3();
+                    instances.push(VoxInstance {
+                        model: models.len(),
+                        rotation: IDENTITY_ROTATION,
+                        translation: IVec3::new(
+                            minimum.x,
+                            1 - minimum.z - tile_shape.z as i32,
+                            minimum.y,
+                        ) + (size / 2).as_ivec3(),
+                    });
+                    models.push(VoxModel { size, voxels });
+                }
+            }
+        }
+
+        Self {
+            models,
+            instances,
+            palette,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the scene with a flat scene graph of one transform and shape per instance. Instance
+    /// rotations are written as the identity.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut children = Vec::new();
+
+        // MagicaVoxel refuses files without models.
+        let empty = VoxModel {
+            size: UVec3::ONE,
+            voxels: Vec::new(),
+        };
+        let models = if self.models.is_empty() {
+            std::slice::from_ref(&empty)
+        } else {
+            &self.models[..]
+        };
+
+        for model in models {
+            let mut size = Vec::new();
+            for component in model.size.to_array() {
+                size.extend_from_slice(&component.to_le_bytes());
+            }
+            write_chunk(&mut children, b"SIZE", &size, &[])?;
+
+            let mut xyzi = Vec::with_capacity(4 + model.voxels.len() * 4);
+            xyzi.extend_from_slice(&(model.voxels.len() as u32).to_le_bytes());
+            for (position, color) in &model.voxels {
+                xyzi.extend_from_slice(&[
+                    position.x as u8,
+                    position.y as u8,
+                    position.z as u8,
+                    *color,
+                ]);
+            }
+            write_chunk(&mut children, b"XYZI", &xyzi, &[])?;
+        }
+
+        // Node 0 is the root transform, node 1 the group holding every instance, and each
+        // instance is a transform followed by a shape.
+        let mut root = Vec::new();
+        write_transform(&mut root, 0, 1, IVec3::ZERO);
+        write_chunk(&mut children, b"nTRN", &root, &[])?;
+
+        let mut group = Vec::new();
+        group.extend_from_slice(&1i32.to_le_bytes());
+        write_dict(&mut group, &[]);
+        group.extend_from_slice(&(self.instances.len() as i32).to_le_bytes());
+        for index in 0..self.instances.len() {
+            group.extend_from_slice(&(2 + index as i32 * 2).to_le_bytes());
+        }
+        write_chunk(&mut children, b"nGRP", &group, &[])?;
+
+        for (index, instance) in self.instances.iter().enumerate() {
+            let id = 2 + index as i32 * 2;
+
+            let mut transform = Vec::new();
+            write_transform(&mut transform, id, id + 1, instance.translation);
+            write_chunk(&mut children, b"nTRN", &transform, &[])?;
+
+            let mut shape = Vec::new();
+            shape.extend_from_slice(&(id + 1).to_le_bytes());
+            write_dict(&mut shape, &[]);
+            shape.extend_from_slice(&1i32.to_le_bytes());
+            shape.extend_from_slice(&(instance.model as i32).to_le_bytes());
+            write_dict(&mut shape, &[]);
+            write_chunk(&mut children, b"nSHP", &shape, &[])?;
+        }
+
+        let mut rgba = Vec::with_capacity(256 * 4);
+        for color in self.palette.iter().skip(1) {
+            rgba.extend_from_slice(color);
+        }
+        rgba.extend_from_slice(&[0; 4]);
+        write_chunk(&mut children, b"RGBA", &rgba, &[])?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        write_chunk(writer, b"MAIN", &[], &children)
+    }
+}
+
+/// Loads a `.vox` file and stamps it into the world with the minimum corner of the scene at
+/// `minimum`, recording the edit in `history`. Empty space in the scene leaves the world
+/// untouched. Returns the extent that was written, or `None` if the scene is empty.
+pub fn import_vox(
+    path: impl AsRef<Path>,
+    registry: &BlockRegistry,
+    mapping: &VoxPaletteMapping,
+    history: &mut EditHistory,
+    world: &mut VoxelWorld,
+    minimum: WorldVoxelPos,
+) -> io::Result<Option<Extent<IVec3>>> {
+    let Some(volume) = VoxFile::load(path)?.to_volume(registry, mapping)? else {
+        return Ok(None);
+    };
+
+    history.merge_voxels(world, minimum, &volume);
+
+    Ok(Some(Extent::from_min_and_shape(
+        minimum.0,
+        volume.shape().as_ivec3(),
+    )))
+}
+
+/// Writes the voxels within a world extent to a `.vox` file. Voxels in chunks that are not
+/// loaded are left empty.
+pub fn export_vox(
+    path: impl AsRef<Path>,
+    registry: &BlockRegistry,
+    world: &VoxelWorld,
+    extent: Extent<IVec3>,
+) -> io::Result<()> {
+    VoxFile::from_volume(&world.get_voxels(extent), registry).save(path)
+}
+
+enum SceneNode {
+    Transform {
+        child: i32,
+        rotation: VoxRotation,
+        translation: IVec3,
+        hidden: bool,
+    },
+    Group {
+        children: Vec<i32>,
+    },
+    Shape {
+        model: i32,
+    },
+}
+
+/// The deepest scene graph accepted, guarding against cycles in malformed files.
+const MAX_SCENE_DEPTH: usize = 64;
+
+/// The most model instances accepted in a scene. Groups may list the same node many times, so
+/// the number of instances could otherwise grow exponentially with the depth of the scene graph.
+const MAX_INSTANCES: usize = 1 << 16;
+
+/// The most nodes visited while walking a scene graph, which also bounds the work spent on shared
+/// nodes that place no instances, such as hidden transforms.
+const MAX_SCENE_VISITS: usize = 1 << 20;
+
+fn collect_instances(
+    nodes: &HashMap<i32, SceneNode>,
+    id: i32,
+    rotation: VoxRotation,
+    translation: IVec3,
+    instances: &mut Vec<VoxInstance>,
+    depth: usize,
+    visits: &mut usize,
+) -> io::Result<()> {
+    if depth > MAX_SCENE_DEPTH {
+        return Err(invalid_data("scene graph is too deep"));
+    }
+
+    *visits += 1;
+    if *visits > MAX_SCENE_VISITS {
+        return Err(invalid_data("scene graph is too large"));
+    }
+
+    let Some(node) = nodes.get(&id) else {
+        return Err(invalid_data(format!("missing scene node {id}")));
+    };
+
+    match node {
+        SceneNode::Transform {
+            child,
+            rotation: local_rotation,
+            translation: local_translation,
+            hidden,
+        } => {
+            if *hidden {
+                return Ok(());
+            }
+
+            collect_instances(
+                nodes,
+                *child,
+                multiply(&rotation, local_rotation),
+                rotate(&rotation, *local_translation) + translation,
+                instances,
+                depth + 1,
+                visits,
+            )
+        }
+        SceneNode::Group { children } => {
+            for child in children {
+                collect_instances(
+                    nodes,
+                    *child,
+                    rotation,
+                    translation,
+                    instances,
+                    depth + 1,
+                    visits,
+                )?;
+            }
+
+            Ok(())
+        }
+        SceneNode::Shape { model } => {
+            if instances.len() >= MAX_INSTANCES {
+                return Err(invalid_data("scene has too many instances"));
+            }
+
+            instances.push(VoxInstance {
+                model: *model as usize,
+                rotation,
+                translation,
+            });
+
+            Ok(())
+        }
+    }
+}
+
+fn rotate(rotation: &VoxRotation, v: IVec3) -> IVec3 {
+    let v = v.to_array();
+    let row = |r: &[i32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+
+    IVec3::new(row(&rotation[0]), row(&rotation[1]), row(&rotation[2]))
+}
+
+fn multiply(a: &VoxRotation, b: &VoxRotation) -> VoxRotation {
+    let mut out = [[0; 3]; 3];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    out
+}
+
+/// Decodes a rotation packed into a byte: bits 0-1 and 2-3 hold the column of the non-zero entry
+/// in the first and second rows, and bits 4-6 hold the sign of each row.
+fn parse_rotation(value: &str) -> io::Result<VoxRotation> {
+    let bits: u8 = value
+        .trim()
+        .parse()
+        .map_err(|_| invalid_data("invalid rotation"))?;
+
+    let first = (bits & 0b11) as usize;
+    let second = ((bits >> 2) & 0b11) as usize;
+    if first > 2 || second > 2 || first == second {
+        return Err(invalid_data("invalid rotation"));
+    }
+    let third = 3 - first - second;
+
+    let mut rotation = [[0; 3]; 3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        rotation[row][column] = if bits & (1 << (4 + row)) != 0 { -1 } else { 1 };
+    }
+
+    Ok(rotation)
+}
+
+fn parse_translation(value: &str) -> io::Result<IVec3> {
+    let mut components = value.split_whitespace().map(str::parse::<i32>);
+    let mut next = || {
+        components
+            .next()
+            .and_then(Result::ok)
+            .ok_or_else(|| invalid_data("invalid translation"))
+    };
+
+    let translation = IVec3::new(next()?, next()?, next()?);
+    if translation.clamp(
+        IVec3::splat(-MAX_TRANSLATION),
+        IVec3::splat(MAX_TRANSLATION),
+    ) != translation
+    {
+        return Err(invalid_data("translation out of range"));
+    }
+
+    Ok(translation)
+}
+
+fn nearest_palette_color(palette: &[[u8; 4]; 256], color: [u8; 4]) -> usize {
+    let distance = |other: &[u8; 4]| -> i32 {
+        (0..3)
+            .map(|i| (other[i] as i32 - color[i] as i32).pow(2))
+            .sum()
+    };
+
+    (1..256)
+        .min_by_key(|index| distance(&palette[*index]))
+        .unwrap_or(1)
+}
+
+fn write_chunk(
+    writer: &mut impl Write,
+    id: &[u8; 4],
+    content: &[u8],
+    children: &[u8],
+) -> io::Result<()> {
+    writer.write_all(id)?;
+    writer.write_all(&(content.len() as u32).to_le_bytes())?;
+    writer.write_all(&(children.len() as u32).to_le_bytes())?;
+    writer.write_all(content)?;
+    writer.write_all(children)
+}
+
+fn write_transform(out: &mut Vec<u8>, id: i32, child: i32, translation: IVec3) {
+    out.extend_from_slice(&id.to_le_bytes());
+    write_dict(out, &[]);
+    out.extend_from_slice(&child.to_le_bytes());
+    out.extend_from_slice(&(-1i32).to_le_bytes());
+    out.extend_from_slice(&(if id == 0 { -1i32 } else { 0 }).to_le_bytes());
+    out.extend_from_slice(&1i32.to_le_bytes());
+
+    let translation = format!("{} {} {}", translation.x, translation.y, translation.z);
+    write_dict(out, &[("_t", &translation)]);
+}
+
+fn write_dict(out: &mut Vec<u8>, entries: &[(&str, &str)]) {
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (key, value) in entries {
+        for string in [key, value] {
+            out.extend_from_slice(&(string.len() as u32).to_le_bytes());
+            out.extend_from_slice(string.as_bytes());
+        }
+    }
+}
+
+struct RawChunk<'a> {
+    id: [u8; 4],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+fn read_chunk<'a>(reader: &mut &'a [u8]) -> io::Result<RawChunk<'a>> {
+    let mut id = [0; 4];
+    reader.read_exact(&mut id)?;
+    let content_len = read_u32(reader)? as usize;
+    let children_len = read_u32(reader)? as usize;
+
+    if reader.len() < content_len.saturating_add(children_len) {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated .vox chunk",
+        ));
+    }
+
+    let (content, rest) = reader.split_at(content_len);
+    let (children, rest) = rest.split_at(children_len);
+    *reader = rest;
+
+    Ok(RawChunk {
+        id,
+        content,
+        children,
+    })
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut &[u8]) -> io::Result<String> {
+    let len = read_u32(reader)? as usize;
+    if reader.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated .vox string",
+        ));
+    }
+
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("invalid .vox string"))
+}
+
+fn read_dict(reader: &mut &[u8]) -> io::Result<HashMap<String, String>> {
+    let count = read_u32(reader)?;
+    let mut dict = HashMap::new();
+
+    for _ in 0..count {
+        let key = read_string(reader)?;
+        let value = read_string(reader)?;
+        dict.insert(key, value);
+    }
+
+    Ok(dict)
+}
+
+/// The palette MagicaVoxel uses for files without an `RGBA` chunk: a 6×6×6 color cube without
+/// black, followed by ramps of red, green, blue and gray.
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0, 0, 0, 0xff]; 256];
+    palette[0] = [0; 4];
+
+    let mut index = 1;
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    for channel in 0..4 {
+        for value in RAMP {
+            palette[index] = match channel {
+                0 => [value, 0, 0, 0xff],
+                1 => [0, value, 0, 0xff],
+                2 => [0, 0, value, 0xff],
+                _ => [value, value, value, 0xff],
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a file with one model whose scene graph doubles the instances at every level.
+    fn doubling_scene(levels: i32) -> Vec<u8> {
+        let mut children = Vec::new();
+        write_chunk(
+            &mut children,
+            b"SIZE",
+            &[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+            &[],
+        )
+        .unwrap();
+        write_chunk(&mut children, b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1], &[]).unwrap();
+
+        // Transform 2n leads to group 2n + 1, which lists transform 2n + 2 twice.
+        for level in 0..levels {
+            let id = level * 2;
+
+            let mut transform = Vec::new();
+            write_transform(&mut transform, id, id + 1, IVec3::ZERO);
+            write_chunk(&mut children, b"nTRN", &transform, &[]).unwrap();
+
+            let mut group = Vec::new();
+            group.extend_from_slice(&(id + 1).to_le_bytes());
+            write_dict(&mut group, &[]);
+            group.extend_from_slice(&2i32.to_le_bytes());
+            group.extend_from_slice(&(id + 2).to_le_bytes());
+            group.extend_from_slice(&(id + 2).to_le_bytes());
+            write_chunk(&mut children, b"nGRP", &group, &[]).unwrap();
+        }
+
+        let mut shape = Vec::new();
+        shape.extend_from_slice(&(levels * 2).to_le_bytes());
+        write_dict(&mut shape, &[]);
+        shape.extend_from_slice(&1i32.to_le_bytes());
+        shape.extend_from_slice(&0i32.to_le_bytes());
+        write_dict(&mut shape, &[]);
+        write_chunk(&mut children, b"nSHP", &shape, &[]).unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_chunk(&mut bytes, b"MAIN", &[], &children).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let definitions: Vec<BlockDefinition> = ron::from_str(
+            r#"[
+                (name: "red", color: Rgba(red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0)),
+                (name: "green", color: Rgba(red: 0.0, green: 1.0, blue: 0.0, alpha: 1.0)),
+                (name: "blue", color: Rgba(red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0)),
+            ]"#,
+        )
+        .unwrap();
+        let registry = BlockRegistry::new(definitions).unwrap();
+
+        // Wider than a model along x, with holes but solid corners so that the scene spans the
+        // whole volume.
+        let shape = UVec3::new(MAX_MODEL_SIZE + 44, 3, 2);
+        let mut volume = VoxelVolume::new(shape);
+        for z in 0..shape.z {
+            for y in 0..shape.y {
+                for x in 0..shape.x {
+                    let corner = (x == 0 || x == shape.x - 1)
+                        && (y == 0 || y == shape.y - 1)
+                        && (z == 0 || z == shape.z - 1);
+                    if corner || (x + 2 * y + 3 * z) % 5 != 0 {
+                        *volume.voxel_at_mut(UVec3::new(x, y, z)) =
+                            Voxel(1 + ((x + y + z) % 3) as u16);
+                    }
+                }
+            }
+        }
+
+        let mut bytes = Vec::new();
+        VoxFile::from_volume(&volume, &registry)
+            .write(&mut bytes)
+            .unwrap();
+        let imported = VoxFile::parse(&bytes)
+            .unwrap()
+            .to_volume(&registry, &VoxPaletteMapping::default())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(imported.shape(), shape);
+        assert_eq!(imported.read_data(), volume.read_data());
+    }
+
+    #[test]
+    fn shared_scene_nodes() {
+        let file = VoxFile::parse(&doubling_scene(4)).unwrap();
+        assert_eq!(file.instances.len(), 16);
+
+        let err = VoxFile::parse(&doubling_scene(30)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,8 +1,11 @@
-use bevy::utils::HashMap;
-use strum::IntoEnumIterator;
+use strum::{EnumCount, IntoEnumIterator};
 
 use super::Biome;
 
+/// How far, in climate space, a biome's influence extends across its borders. Smaller values
+/// give sharper transitions.
+const BLEND_DISTANCE: f32 = 0.08;
+
 #[derive(Clone, Copy)]
 pub struct BiomeConditions {
     pub temperature: f32,
@@ -15,24 +18,30 @@ impl BiomeConditions {
             .sqrt()
     }
 
-    pub fn get_biomes(self) -> HashMap<Biome, f32> {
-        let mut absolute_map = HashMap::new();
-        let mut total: f32 = 0.0;
+    /// Returns the weight of every biome under these conditions, indexed by [`Biome`] and
+    /// summing to 1. The nearest biome has the largest weight, and weights change continuously
+    /// with the conditions so that terrain blends smoothly across biome borders.
+    pub fn get_biomes(self) -> [f32; Biome::COUNT] {
+        let mut weights = [0.0; Biome::COUNT];
 
-        for biome in Biome::iter() {
-            let difference = self.difference(biome.get_conditions());
-            absolute_map.insert(biome, difference);
-            total += difference;
-        }
+        let nearest = Biome::iter()
+            .map(|biome| self.difference(biome.get_conditions()).powi(2))
+            .fold(f32::INFINITY, f32::min);
 
-        absolute_map.retain(|_, difference| *difference / total < 0.01);
+        let mut total = 0.0;
+        for biome in Biome::iter() {
+            // Relative to the nearest biome, so the weights never all underflow.
+            let difference = self.difference(biome.get_conditions()).powi(2) - nearest;
+            let weight = (-difference / BLEND_DISTANCE.powi(2)).exp();
 
-        let mut relative_map = HashMap::new();
+            weights[biome as usize] = weight;
+            total += weight;
+        }
 
-        for (biome, difference) in absolute_map {
-            relative_map.insert(biome, 1.0 - difference / total);
+        for weight in &mut weights {
+            *weight /= total;
         }
 
-        relative_map
+        weights
     }
 }
@@ -1,23 +1,40 @@
 mod conditions;
 
-use strum::EnumIter;
+use noise::{Clamp, Fbm, MultiFractal, NoiseFn, OpenSimplex, ScaleBias};
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
+
+use crate::prelude::*;
 
 use self::conditions::BiomeConditions;
 
-pub trait BiomeGenerator {
-    fn get_biome(&self) -> Biome;
+use super::{
+    seed::WorldSeed,
+    terrain::{require_block, sample_columns, NoiseGraph},
+};
+
+/// The spacing, in blocks, at which temperature and humidity are sampled.
+const CLIMATE_SAMPLE_SPACING: i32 = 8;
+
+/// Columns at most this high are covered in sand, forming beaches and sea floors.
+const SHORE_HEIGHT: i32 = 1;
+
+pub trait BiomeGenerator: Sync {
+    /// Shapes the height of a column from height noise in `[-1, 1]`.
+    fn height(&self, noise: f64) -> f64;
+    /// The name of the block covering the surface.
+    fn surface_block(&self) -> &'static str;
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter, EnumCount)]
 pub enum Biome {
     TropicalRainforest,
-    // TemperateRainforest,
-    // Savannah,
-    // TemperateDeciduousForest,
-    // Taiga,
-    // Chaparral,
-    // SubtropicalDesert,
-    // ColdDesert,
+    TemperateRainforest,
+    Savannah,
+    TemperateDeciduousForest,
+    Taiga,
+    Chaparral,
+    SubtropicalDesert,
+    ColdDesert,
     Tundra,
 }
 
@@ -28,34 +45,34 @@ impl Biome {
                 temperature: 0.90,
                 humidity: 0.75,
             },
-            // Biome::TemperateRainforest => BiomeConditions {
-            //     temperature: 0.60,
-            //     humidity: 0.60,
-            // },
-            // Biome::Savannah => BiomeConditions {
-            //     temperature: 0.90,
-            //     humidity: 0.40,
-            // },
-            // Biome::TemperateDeciduousForest => BiomeConditions {
-            //     temperature: 0.60,
-            //     humidity: 0.30,
-            // },
-            // Biome::Taiga => BiomeConditions {
-            //     temperature: 0.20,
-            //     humidity: 0.20,
-            // },
-            // Biome::Chaparral => BiomeConditions {
-            //     temperature: 0.50,
-            //     humidity: 0.10,
-            // },
-            // Biome::SubtropicalDesert => BiomeConditions {
-            //     temperature: 0.90,
-            //     humidity: 0.05,
-            // },
-            // Biome::ColdDesert => BiomeConditions {
-            //     temperature: 0.50,
-            //     humidity: 0.05,
-            // },
+            Biome::TemperateRainforest => BiomeConditions {
+                temperature: 0.60,
+                humidity: 0.60,
+            },
+            Biome::Savannah => BiomeConditions {
+                temperature: 0.90,
+                humidity: 0.40,
+            },
+            Biome::TemperateDeciduousForest => BiomeConditions {
+                temperature: 0.60,
+                humidity: 0.30,
+            },
+            Biome::Taiga => BiomeConditions {
+                temperature: 0.20,
+                humidity: 0.20,
+            },
+            Biome::Chaparral => BiomeConditions {
+                temperature: 0.50,
+                humidity: 0.10,
+            },
+            Biome::SubtropicalDesert => BiomeConditions {
+                temperature: 0.90,
+                humidity: 0.05,
+            },
+            Biome::ColdDesert => BiomeConditions {
+                temperature: 0.50,
+                humidity: 0.05,
+            },
             Biome::Tundra => BiomeConditions {
                 temperature: 0.0,
                 humidity: 0.0,
@@ -64,6 +81,159 @@ impl Biome {
     }
 
     fn generator(&self) -> &'static dyn BiomeGenerator {
-        todo!()
+        &GENERATORS[*self as usize]
     }
 }
+
+/// A biome that scales and offsets the height noise.
+struct BiomeShape {
+    height_offset: f64,
+    height_scale: f64,
+    surface_block: &'static str,
+}
+
+impl BiomeGenerator for BiomeShape {
+    fn height(&self, noise: f64) -> f64 {
+        noise.mul_add(self.height_scale, self.height_offset)
+    }
+
+    fn surface_block(&self) -> &'static str {
+        self.surface_block
+    }
+}
+
+/// The generator of each biome, indexed by [`Biome`].
+static GENERATORS: [BiomeShape; Biome::COUNT] = [
+    // TropicalRainforest
+    BiomeShape {
+        height_offset: 4.0,
+        height_scale: 60.0,
+        surface_block: "grass",
+    },
+    // TemperateRainforest
+    BiomeShape {
+        height_offset: 8.0,
+        height_scale: 110.0,
+        surface_block: "grass",
+    },
+    // Savannah
+    BiomeShape {
+        height_offset: 6.0,
+        height_scale: 40.0,
+        surface_block: "dry_grass",
+    },
+    // TemperateDeciduousForest
+    BiomeShape {
+        height_offset: 6.0,
+        height_scale: 80.0,
+        surface_block: "grass",
+    },
+    // Taiga
+    BiomeShape {
+        height_offset: 10.0,
+        height_scale: 120.0,
+        surface_block: "snow",
+    },
+    // Chaparral
+    BiomeShape {
+        height_offset: 8.0,
+        height_scale: 70.0,
+        surface_block: "dry_grass",
+    },
+    // SubtropicalDesert
+    BiomeShape {
+        height_offset: 4.0,
+        height_scale: 30.0,
+        surface_block: "sand",
+    },
+    // ColdDesert
+    BiomeShape {
+        height_offset: 6.0,
+        height_scale: 50.0,
+        surface_block: "sand",
+    },
+    // Tundra
+    BiomeShape {
+        height_offset: 2.0,
+        height_scale: 50.0,
+        surface_block: "snow",
+    },
+];
+
+/// Temperature and humidity fields, from which every column gets a blend of biomes.
+pub struct BiomeMap {
+    temperature: NoiseGraph,
+    humidity: NoiseGraph,
+    surface_blocks: [Voxel; Biome::COUNT],
+    sand: Voxel,
+}
+
+impl BiomeMap {
+    pub fn new(registry: &BlockRegistry, seed: WorldSeed) -> Self {
+        let mut surface_blocks = [Voxel::EMPTY; Biome::COUNT];
+        for biome in Biome::iter() {
+            surface_blocks[biome as usize] =
+                require_block(registry, biome.generator().surface_block());
+        }
+
+        Self {
+            temperature: Box::new(climate_noise(seed.derive("temperature").noise_seed())),
+            humidity: Box::new(climate_noise(seed.derive("humidity").noise_seed())),
+            surface_blocks,
+            sand: require_block(registry, "sand"),
+        }
+    }
+
+    /// Returns the biome weights of every padded column of a chunk, in the order of
+    /// [`Heightmap::iter`].
+    pub fn weights(&self, origin: ColumnPos) -> Vec<[f32; Biome::COUNT]> {
+        let temperature = sample_columns(&*self.temperature, origin, CLIMATE_SAMPLE_SPACING);
+        let humidity = sample_columns(&*self.humidity, origin, CLIMATE_SAMPLE_SPACING);
+
+        temperature
+            .into_iter()
+            .zip(humidity)
+            .map(|(temperature, humidity)| {
+                BiomeConditions {
+                    temperature: temperature as f32,
+                    humidity: humidity as f32,
+                }
+                .get_biomes()
+            })
+            .collect()
+    }
+
+    /// Shapes the height of a column, blending the shape of each biome by its weight.
+    pub fn height(&self, weights: &[f32; Biome::COUNT], noise: f64) -> f64 {
+        Biome::iter()
+            .map(|biome| weights[biome as usize] as f64 * biome.generator().height(noise))
+            .sum()
+    }
+
+    /// Returns the block covering a column: sand near and below sea level, and otherwise the
+    /// surface block of the column's strongest biome.
+    pub fn surface_block(&self, weights: &[f32; Biome::COUNT], height: i32) -> Voxel {
+        if height <= SHORE_HEIGHT {
+            return self.sand;
+        }
+
+        let strongest = Biome::iter()
+            .max_by(|a, b| weights[*a as usize].total_cmp(&weights[*b as usize]))
+            .unwrap();
+
+        self.surface_blocks[strongest as usize]
+    }
+}
+
+/// Large-scale noise in `[0, 1]`, stretched so that the climate reaches its extremes.
+fn climate_noise(seed: u32) -> impl NoiseFn<f64, 2> + Send + Sync {
+    let noise = Fbm::<OpenSimplex>::new(seed)
+        .set_octaves(3)
+        .set_frequency(0.0008)
+        .set_persistence(0.5)
+        .set_lacunarity(2.0);
+
+    let noise = ScaleBias::new(noise).set_scale(0.75).set_bias(0.5);
+
+    Clamp::new(noise).set_bounds(0.0, 1.0)
+}
@@ -0,0 +1,359 @@
+use std::{
+    f32::consts::{PI, TAU},
+    sync::Arc,
+};
+
+use ndshape::Shape;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex};
+
+use crate::prelude::*;
+
+use super::{
+    seed::{splitmix64, WorldSeed},
+    terrain::{require_block, sample_volume, SEA_LEVEL},
+};
+
+/// The spacing, in blocks, at which cave noise is sampled along each axis.
+const CAVE_SAMPLE_SPACING: IVec3 = IVec3::new(4, 4, 4);
+
+/// Cheese caves, large open caverns, are carved where their noise is above this.
+const CHEESE_THRESHOLD: f64 = 0.45;
+
+/// Noodle caves are carved where both of their noise fields are within this of zero, which
+/// traces thin winding tunnels along the curves where the two surfaces meet.
+const NOODLE_WIDTH: f64 = 0.04;
+
+/// The solid blocks kept between caves and the floor of columns that may be under water.
+const OCEAN_ROOF: i32 = 6;
+
+/// Worms start in square cells of this many blocks.
+const WORM_CELL_SIZE: i32 = 128;
+const WORMS_PER_CELL: i32 = 2;
+const WORM_STEPS: u32 = 96;
+const WORM_STEP_LENGTH: f32 = 1.5;
+const WORM_MIN_RADIUS: f32 = 1.5;
+const WORM_MAX_RADIUS: f32 = 3.5;
+/// Worms start between these heights.
+const WORM_MIN_Y: i32 = -128;
+const WORM_MAX_Y: i32 = 16;
+/// The farthest a worm carves from where it starts.
+const WORM_REACH: f32 = WORM_STEPS as f32 * WORM_STEP_LENGTH + WORM_MAX_RADIUS;
+
+/// The offsets of the columns whose heights decide the cave ceiling of a column.
+pub const NEIGHBOUR_COLUMNS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Carves caves out of generated terrain.
+///
+/// Caves only depend on the world seed and the world position of each voxel, never on which
+/// chunk is being generated, so they line up across chunk borders and in the padding. Noodle and
+/// cheese caves are sampled from 3D noise. Worm tunnels start at random positions in cells
+/// aligned to world coordinates, and every chunk walks the worms of all cells within their reach
+/// to carve its part of them.
+///
+/// Water is never carved, and columns that may be under water or next to water keep a solid roof
+/// over their caves so that neither the sea floor nor the shore is breached.
+pub struct CaveCarver {
+    worm_seed: WorldSeed,
+    cheese: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
+    noodles: [Box<dyn NoiseFn<f64, 3> + Send + Sync>; 2],
+    surface_margin: i32,
+    water: Voxel,
+}
+
+impl CaveCarver {
+    /// `surface_margin` is how far the terrain's surface may be from its heightmap.
+    pub fn new(registry: &BlockRegistry, seed: WorldSeed, surface_margin: i32) -> Self {
+        Self {
+            worm_seed: seed.derive("worms"),
+            cheese: Box::new(cave_noise(seed.derive("cheese").noise_seed(), 0.012, 2)),
+            noodles: [
+                Box::new(cave_noise(seed.derive("noodle_a").noise_seed(), 0.02, 1)),
+                Box::new(cave_noise(seed.derive("noodle_b").noise_seed(), 0.02, 1)),
+            ],
+            surface_margin,
+            water: require_block(registry, "water"),
+        }
+    }
+
+    /// Returns whether a chunk has anything that caves could be carved out of.
+    pub fn is_carvable(&self, chunk: &VoxelChunk) -> bool {
+        !matches!(chunk.as_uniform(), Some(voxel) if voxel == Voxel::EMPTY || voxel == self.water)
+    }
+
+    /// Carves the caves within a chunk, padding included. `neighbours` are the heightmaps of the
+    /// columns next to the chunk's, in the order of [`NEIGHBOUR_COLUMNS`].
+    pub fn carve(
+        &self,
+        origin: ChunkPos,
+        heightmap: &Heightmap,
+        neighbours: &[Arc<Heightmap>; 4],
+        chunk: &mut VoxelChunk,
+    ) {
+        let origin = origin.padded_origin().0;
+
+        if !self.is_carvable(chunk) {
+            return;
+        }
+
+        let ceilings = self.ceilings(heightmap, neighbours);
+
+        self.carve_noise(origin, &ceilings, chunk);
+        self.carve_worms(origin, &ceilings, chunk);
+    }
+
+    /// Returns the height below which caves may be carved in every padded column, in the order of
+    /// [`Heightmap::iter`].
+    ///
+    /// Water may lie over a column whose floor is below the sea level, and caves in the columns
+    /// around it would open into it sideways, so those columns are only carved well below the
+    /// lowest floor among them. The neighbours of border columns are read from the heightmaps of
+    /// the columns next to this one, so every column gets the same ceiling in every chunk.
+    fn ceilings(&self, heightmap: &Heightmap, neighbours: &[Arc<Heightmap>; 4]) -> Vec<i32> {
+        let size = IVec2::splat(PADDED_CHUNK_SIZE as i32);
+
+        let height = |column: IVec2| {
+            let offset = IVec2::select(column.cmplt(IVec2::ZERO), IVec2::NEG_ONE, IVec2::ZERO)
+                + IVec2::select(column.cmpge(size), IVec2::ONE, IVec2::ZERO);
+            let local = (column - offset * CHUNK_SIZE as i32).as_uvec2();
+
+            match NEIGHBOUR_COLUMNS
+                .iter()
+                .position(|neighbour| *neighbour == offset)
+            {
+                Some(index) => neighbours[index].get(local),
+                None => heightmap.get(local),
+            }
+        };
+
+        heightmap
+            .iter()
+            .map(|(column, column_height)| {
+                let lowest = NEIGHBOUR_COLUMNS
+                    .into_iter()
+                    .map(|offset| height(column.as_ivec2() + offset))
+                    .fold(column_height, i32::min);
+
+                let floor = lowest - self.surface_margin;
+                if floor >= SEA_LEVEL {
+                    i32::MAX
+                } else {
+                    floor - OCEAN_ROOF
+                }
+            })
+            .collect()
+    }
+
+    /// Returns whether a voxel at height `y` in a column with the given ceiling may be carved.
+    fn can_carve(&self, voxel: Voxel, y: i32, ceiling: i32) -> bool {
+        voxel != Voxel::EMPTY && voxel != self.water && y < ceiling
+    }
+
+    fn carve_noise(&self, origin: IVec3, ceilings: &[i32], chunk: &mut VoxelChunk) {
+        let shape = UVec3::splat(PADDED_CHUNK_SIZE);
+        let cheese = sample_volume(&*self.cheese, origin, shape, CAVE_SAMPLE_SPACING);
+        let noodle_a = sample_volume(&*self.noodles[0], origin, shape, CAVE_SAMPLE_SPACING);
+        let noodle_b = sample_volume(&*self.noodles[1], origin, shape, CAVE_SAMPLE_SPACING);
+
+        for index in 0..cheese.len() {
+            let carved = cheese[index] > CHEESE_THRESHOLD
+                || (noodle_a[index].abs() < NOODLE_WIDTH && noodle_b[index].abs() < NOODLE_WIDTH);
+            if !carved {
+                continue;
+            }
+
+            let [x, y, z] = CHUNK_SHAPE.delinearize(index as u32);
+            let ceiling = ceilings[FLAT_CHUNK_SHAPE.linearize([x, z]) as usize];
+            if self.can_carve(
+                chunk.voxels.voxel_at_index(index),
+                origin.y + y as i32,
+                ceiling,
+            ) {
+                chunk.voxels.set_voxel_at_index(index, Voxel::EMPTY);
+            }
+        }
+    }
+
+    fn carve_worms(&self, origin: IVec3, ceilings: &[i32], chunk: &mut VoxelChunk) {
+        let size = PADDED_CHUNK_SIZE as i32;
+        let reach = WORM_REACH.ceil() as i32;
+
+        if origin.y > WORM_MAX_Y + reach || origin.y + size < WORM_MIN_Y - reach {
+            return;
+        }
+
+        let cell = |position: i32| position.div_euclid(WORM_CELL_SIZE);
+        let minimum_cell = IVec2::new(cell(origin.x - reach), cell(origin.z - reach));
+        let maximum_cell = IVec2::new(cell(origin.x + size + reach), cell(origin.z + size + reach));
+
+        let chunk_minimum = origin.as_vec3();
+        let chunk_maximum = (origin + size).as_vec3();
+
+        for cell_z in minimum_cell.y..=maximum_cell.y {
+            for cell_x in minimum_cell.x..=maximum_cell.x {
+                for worm in 0..WORMS_PER_CELL {
+                    let mut rng = CaveRng(
+                        self.worm_seed
+                            .position_seed(IVec3::new(cell_x, worm, cell_z)),
+                    );
+
+                    let start = Vec3::new(
+                        (cell_x as f32 + rng.next_f32()) * WORM_CELL_SIZE as f32,
+                        WORM_MIN_Y as f32 + rng.next_f32() * (WORM_MAX_Y - WORM_MIN_Y) as f32,
+                        (cell_z as f32 + rng.next_f32()) * WORM_CELL_SIZE as f32,
+                    );
+
+                    let nearest = start.clamp(chunk_minimum, chunk_maximum);
+                    if nearest.distance(start) <= WORM_REACH {
+                        self.carve_worm(&mut rng, start, origin, ceilings, chunk);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Walks a worm from `start`, carving a tunnel that widens in the middle.
+    fn carve_worm(
+        &self,
+        rng: &mut CaveRng,
+        start: Vec3,
+        origin: IVec3,
+        ceilings: &[i32],
+        chunk: &mut VoxelChunk,
+    ) {
+        let mut position = start;
+        let mut yaw = rng.next_f32() * TAU;
+        let mut pitch = (rng.next_f32() - 0.5) * 0.5;
+        let mut yaw_change = 0.0;
+        let mut pitch_change = 0.0;
+
+        for step in 0..WORM_STEPS {
+            let radius = WORM_MIN_RADIUS
+                + (WORM_MAX_RADIUS - WORM_MIN_RADIUS)
+                    * (PI * step as f32 / WORM_STEPS as f32).sin();
+            self.carve_sphere(position, radius, origin, ceilings, chunk);
+
+            position += Vec3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            ) * WORM_STEP_LENGTH;
+
+            // Keep worms mostly level, turning smoothly.
+            pitch = pitch * 0.7 + pitch_change * 0.1;
+            yaw += yaw_change * 0.1;
+            pitch_change = pitch_change * 0.9 + (rng.next_f32() - rng.next_f32()) * 2.0;
+            yaw_change = yaw_change * 0.75 + (rng.next_f32() - rng.next_f32()) * 4.0;
+        }
+    }
+
+    fn carve_sphere(
+        &self,
+        center: Vec3,
+        radius: f32,
+        origin: IVec3,
+        ceilings: &[i32],
+        chunk: &mut VoxelChunk,
+    ) {
+        let minimum = ((center - radius).floor().as_ivec3() - origin).max(IVec3::ZERO);
+        let maximum = ((center + radius).ceil().as_ivec3() - origin)
+            .min(IVec3::splat(PADDED_CHUNK_SIZE as i32 - 1));
+
+        if minimum.cmpgt(maximum).any() {
+            return;
+        }
+
+        for z in minimum.z..=maximum.z {
+            for x in minimum.x..=maximum.x {
+                let ceiling = ceilings[FLAT_CHUNK_SHAPE.linearize([x as u32, z as u32]) as usize];
+
+                for y in minimum.y..=maximum.y {
+                    let local = IVec3::new(x, y, z);
+                    let voxel_center = (origin + local).as_vec3() + 0.5;
+                    if voxel_center.distance_squared(center) > radius * radius {
+                        continue;
+                    }
+
+                    let local = local.as_uvec3();
+                    if self.can_carve(chunk.voxels.voxel_at(local), origin.y + y, ceiling) {
+                        chunk.voxels.set_voxel_at(local, Voxel::EMPTY);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn cave_noise(seed: u32, frequency: f64, octaves: usize) -> impl NoiseFn<f64, 3> + Send + Sync {
+    Fbm::<OpenSimplex>::new(seed)
+        .set_octaves(octaves)
+        .set_frequency(frequency)
+        .set_persistence(0.5)
+        .set_lacunarity(2.0)
+}
+
+/// A small deterministic random number generator for placing worms.
+struct CaveRng(u64);
+
+impl CaveRng {
+    /// Returns a number in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        self.0 = splitmix64(self.0);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carver() -> CaveCarver {
+        let definitions: Vec<BlockDefinition> = ron::from_str(r#"[(name: "water")]"#).unwrap();
+        let registry = BlockRegistry::new(definitions).unwrap();
+
+        CaveCarver::new(&registry, WorldSeed::Seeded(1), 0)
+    }
+
+    fn heightmap(column: ColumnPos, height: impl Fn(IVec2) -> i32) -> Arc<Heightmap> {
+        let origin = column.padded_origin();
+        let mut heightmap = Heightmap::new();
+        for (position, column_height) in heightmap.iter_mut() {
+            *column_height = height(origin + position.as_ivec2());
+        }
+
+        Arc::new(heightmap)
+    }
+
+    fn ceilings(carver: &CaveCarver, column: ColumnPos, height: impl Fn(IVec2) -> i32) -> Vec<i32> {
+        let neighbours = NEIGHBOUR_COLUMNS.map(|offset| heightmap(column + offset, &height));
+
+        carver.ceilings(&heightmap(column, &height), &neighbours)
+    }
+
+    #[test]
+    fn border_ceilings_match_across_columns() {
+        let carver = carver();
+        // A trench under the sea just outside the padding of the column at x = 1.
+        let height = |column: IVec2| {
+            if column.x == 62 {
+                SEA_LEVEL - 20
+            } else {
+                SEA_LEVEL + 10
+            }
+        };
+
+        let left = ceilings(&carver, ColumnPos::new(0, 0), height);
+        let right = ceilings(&carver, ColumnPos::new(1, 0), height);
+
+        // World x 63 and 64 are in both columns, at local x 64 and 65 on the left and 0 and 1 on
+        // the right.
+        for z in 0..PADDED_CHUNK_SIZE {
+            for (left_x, right_x) in [(64, 0), (65, 1)] {
+                assert_eq!(
+                    left[FLAT_CHUNK_SHAPE.linearize([left_x, z]) as usize],
+                    right[FLAT_CHUNK_SHAPE.linearize([right_x, z]) as usize],
+                );
+            }
+        }
+        assert!(right[FLAT_CHUNK_SHAPE.linearize([0, 0]) as usize] < SEA_LEVEL);
+    }
+}
@@ -6,35 +6,33 @@ use crate::{
     render::mesh::chunk::MeshChunkQueue,
     world::chunk::{Chunk, ChunkEntityMap},
 };
-use bevy::{
-    math::Vec3Swizzles,
-    tasks::{AsyncComputeTaskPool, Task},
-};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
 use futures_lite::future::{block_on, poll_once};
 use futures_util::FutureExt;
 
 use super::{
-    terrain::{standard::StandardTerrainGenerator, TerrainGenerator},
+    caves::{CaveCarver, NEIGHBOUR_COLUMNS},
+    terrain::TerrainGenerator,
     world::VoxelWorldGenerator,
     GenerationSettings,
 };
 
 pub struct ChunkGenerator {
-    heightmap_cache: FutureTaskCache<IVec2, Heightmap>,
+    heightmap_cache: FutureTaskCache<ColumnPos, Heightmap>,
     terrain_generator: Arc<dyn TerrainGenerator>,
+    caves: Option<CaveCarver>,
 }
 
-impl Default for ChunkGenerator {
-    fn default() -> Self {
+impl ChunkGenerator {
+    pub fn new(terrain_generator: Arc<dyn TerrainGenerator>, caves: Option<CaveCarver>) -> Self {
         Self {
             heightmap_cache: FutureTaskCache::default(),
-            terrain_generator: Arc::new(StandardTerrainGenerator::default()),
+            terrain_generator,
+            caves,
         }
     }
-}
 
-impl ChunkGenerator {
-    pub async fn generate_heightmap(&self, origin: IVec2) -> Arc<Heightmap> {
+    pub async fn generate_heightmap(&self, origin: ColumnPos) -> Arc<Heightmap> {
         if let Some(result) = self.heightmap_cache.get(&origin) {
             match result {
                 FutureCacheResult::Hit(heightmap) => heightmap,
@@ -52,13 +50,38 @@ impl ChunkGenerator {
         }
     }
 
-    pub async fn generate_chunk(&self, origin: IVec3) -> VoxelChunk {
-        let mut chunk = VoxelChunk::default();
+    /// Returns the heightmaps of the columns next to a column, in the order of
+    /// [`NEIGHBOUR_COLUMNS`].
+    async fn generate_neighbour_heightmaps(&self, origin: ColumnPos) -> [Arc<Heightmap>; 4] {
+        let [a, b, c, d] = NEIGHBOUR_COLUMNS.map(|offset| self.generate_heightmap(origin + offset));
 
-        let heightmap = self.generate_heightmap(origin.xz()).await;
+        [a.await, b.await, c.await, d.await]
+    }
 
-        self.terrain_generator
-            .generate_terrain(origin, &heightmap, &mut chunk);
+    pub async fn generate_chunk(&self, origin: ChunkPos) -> VoxelChunk {
+        let heightmap = self.generate_heightmap(origin.column()).await;
+
+        let mut chunk = match self.terrain_generator.uniform_chunk(origin, &heightmap) {
+            Some(voxel) => VoxelChunk::uniform(voxel),
+            None => {
+                let mut chunk = VoxelChunk::default();
+                self.terrain_generator
+                    .generate_terrain(origin, &heightmap, &mut chunk);
+                chunk
+            }
+        };
+
+        // Solid chunks may still have caves running through them.
+        if let Some(caves) = self
+            .caves
+            .as_ref()
+            .filter(|caves| caves.is_carvable(&chunk))
+        {
+            let neighbours = self.generate_neighbour_heightmaps(origin.column()).await;
+            caves.carve(origin, &heightmap, &neighbours, &mut chunk);
+        }
+
+        chunk.voxels.compact();
 
         chunk
     }
@@ -69,13 +92,20 @@ pub struct ChunkGenerationPlugin;
 impl Plugin for ChunkGenerationPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ChunkGenerationQueue>()
+            .add_event::<ChunkGenerated>()
             .add_systems(Update, (handle_queue, handle_tasks, update_center));
     }
 }
 
 pub struct GenerateChunk;
 
-pub type ChunkGenerationQueue = DistanceOrderedQueue<IVec3, GenerateChunk>;
+pub type ChunkGenerationQueue = DistanceOrderedQueue<ChunkPos, GenerateChunk>;
+
+/// Sent when a chunk has been generated and inserted into the [`VoxelWorld`].
+#[derive(Clone, Copy, Debug, Event)]
+pub struct ChunkGenerated {
+    pub position: ChunkPos,
+}
 
 #[derive(Component)]
 pub struct ChunkGenerationTask {
@@ -88,7 +118,7 @@ fn update_center(
 ) {
     let camera = camera.single();
 
-    let center = camera.translation().as_ivec3() / CHUNK_SIZE as i32;
+    let center = ChunkPos::from_translation(camera.translation());
 
     queue.update_center(center)
 }
@@ -130,12 +160,17 @@ fn handle_tasks(
     mut voxel_world: ResMut<VoxelWorld>,
     mut tasks: Query<(Entity, &Chunk, &mut ChunkGenerationTask)>,
     mut mesh_queue: ResMut<MeshChunkQueue>,
+    mut generated_events: EventWriter<ChunkGenerated>,
 ) {
     for (entity, chunk, mut task) in &mut tasks {
         if let Some(voxel_chunk) = block_on(poll_once(&mut task.task)) {
             voxel_world.insert(chunk.position, voxel_chunk);
+            voxel_world.mark_unsaved(chunk.position);
             commands.entity(entity).remove::<ChunkGenerationTask>();
             mesh_queue.push(chunk.position);
+            generated_events.send(ChunkGenerated {
+                position: chunk.position,
+            });
         }
     }
 }
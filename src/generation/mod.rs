@@ -1,6 +1,8 @@
 mod biomes;
+pub mod caves;
 pub mod chunk;
 pub mod conditions;
+pub mod seed;
 pub mod terrain;
 pub mod world;
 
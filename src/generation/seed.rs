@@ -0,0 +1,57 @@
+use crate::prelude::*;
+
+/// The seed terrain generation draws from. Each noise source derives its own seed from it by
+/// name, so adding a layer never changes the output of the others and the same world seed always
+/// reproduces the same terrain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WorldSeed {
+    Seeded(u64),
+    /// Every noise source uses seed 0, as in worlds created before seeds were used.
+    Legacy,
+}
+
+impl WorldSeed {
+    /// Derives the seed of a named noise source or layer.
+    pub fn derive(self, name: &str) -> Self {
+        match self {
+            Self::Seeded(seed) => Self::Seeded(splitmix64(seed ^ splitmix64(fnv1a(name)))),
+            Self::Legacy => Self::Legacy,
+        }
+    }
+
+    /// Derives a seed for a feature placed at a position rather than sampled from noise.
+    pub fn position_seed(self, position: IVec3) -> u64 {
+        let seed = match self {
+            Self::Seeded(seed) => seed,
+            Self::Legacy => 0,
+        };
+
+        [position.x, position.y, position.z]
+            .into_iter()
+            .fold(seed, |hash, value| splitmix64(hash ^ value as u32 as u64))
+    }
+
+    /// Returns the seed to give to a noise function.
+    #[inline]
+    pub fn noise_seed(self) -> u32 {
+        match self {
+            Self::Seeded(seed) => (seed >> 32) as u32,
+            Self::Legacy => 0,
+        }
+    }
+}
+
+/// Mixes the bits of a value so that nearby inputs give unrelated outputs.
+pub fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hashes a name the same way on every platform and build, unlike the standard library hasher.
+fn fnv1a(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
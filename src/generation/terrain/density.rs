@@ -0,0 +1,122 @@
+use noise::{Clamp, Fbm, MultiFractal, NoiseFn, OpenSimplex};
+
+use crate::{generation::seed::WorldSeed, prelude::*};
+
+use super::{
+    require_block, sample_volume, standard::StandardTerrainGenerator, TerrainGenerator, SEA_LEVEL,
+};
+
+/// The spacing, in blocks, at which the density noise is sampled along each axis.
+const DENSITY_SAMPLE_SPACING: IVec3 = IVec3::new(4, 8, 4);
+
+/// How far, in blocks, the density noise can move the surface away from the heightmap.
+pub const DENSITY_FALLOFF: i32 = 24;
+
+/// Generates terrain from a density function: a voxel is solid where the density is positive.
+///
+/// The density is the height of the standard terrain above the voxel, divided by
+/// [`DENSITY_FALLOFF`], plus 3D noise in `[-1, 1]`. Far from the surface the height dominates,
+/// so the ground stays solid and the sky stays empty, while near it the noise carves overhangs,
+/// arches and cliffs. The standard heightmap is where the noise is zero, so it stays a close
+/// approximation of the surface for distant terrain and heightmap culling.
+pub struct DensityTerrainGenerator {
+    base: StandardTerrainGenerator,
+    density: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
+    stone: Voxel,
+    water: Voxel,
+}
+
+impl DensityTerrainGenerator {
+    pub fn new(registry: &BlockRegistry, seed: WorldSeed) -> Self {
+        Self {
+            base: StandardTerrainGenerator::new(registry, seed),
+            density: Box::new(density_noise(seed.derive("density").noise_seed())),
+            stone: require_block(registry, "stone"),
+            water: require_block(registry, "water"),
+        }
+    }
+}
+
+fn density_noise(seed: u32) -> impl NoiseFn<f64, 3> + Send + Sync {
+    let noise = Fbm::<OpenSimplex>::new(seed)
+        .set_octaves(3)
+        .set_frequency(0.015)
+        .set_persistence(0.5)
+        .set_lacunarity(2.0);
+
+    // The bounds let whole chunks far from the surface be skipped.
+    Clamp::new(noise).set_bounds(-1.0, 1.0)
+}
+
+impl TerrainGenerator for DensityTerrainGenerator {
+    fn generate_heightmap(&self, origin: ColumnPos) -> Heightmap {
+        self.base.generate_heightmap(origin)
+    }
+
+    fn generate_terrain(&self, origin: ChunkPos, heightmap: &Heightmap, chunk: &mut VoxelChunk) {
+        let origin = origin.padded_origin().0;
+        let size = PADDED_CHUNK_SIZE;
+
+        // One layer above the chunk to find the surface of its top layer.
+        let shape = UVec3::new(size, size + 1, size);
+        let noise = sample_volume(&*self.density, origin, shape, DENSITY_SAMPLE_SPACING);
+
+        let is_solid = |position: UVec3, height: i32| {
+            let index = position.x + shape.x * (position.y + shape.y * position.z);
+            let above = height - (origin.y + position.y as i32);
+
+            above as f64 / DENSITY_FALLOFF as f64 + noise[index as usize] > 0.0
+        };
+
+        for (column, height) in heightmap.iter() {
+            let surface = heightmap.surface(column);
+            let mut covered = is_solid(column.extend_y(size), height);
+
+            for y in (0..size).rev() {
+                let position = column.extend_y(y);
+                let solid = is_solid(position, height);
+
+                let voxel = if solid {
+                    if covered {
+                        self.stone
+                    } else {
+                        surface
+                    }
+                } else if origin.y + (y as i32) < SEA_LEVEL {
+                    self.water
+                } else {
+                    Voxel::EMPTY
+                };
+
+                if voxel != Voxel::EMPTY {
+                    chunk.voxels.set_voxel_at(position, voxel);
+                }
+
+                covered = solid;
+            }
+        }
+    }
+
+    fn uniform_chunk(&self, origin: ChunkPos, heightmap: &Heightmap) -> Option<Voxel> {
+        let (minimum, maximum) = heightmap.bounds();
+        let bottom = origin.padded_origin().0.y;
+        let top = bottom + PADDED_CHUNK_SIZE as i32;
+
+        // The noise is within [-1, 1], so the density has a fixed sign more than the falloff
+        // away from the heightmap. The layer above the chunk must be solid too, or the top
+        // layer would be surface.
+        if minimum - top > DENSITY_FALLOFF {
+            Some(self.stone)
+        } else if maximum + DENSITY_FALLOFF <= bottom {
+            if bottom >= SEA_LEVEL {
+                Some(Voxel::EMPTY)
+            } else if top <= SEA_LEVEL {
+                Some(self.water)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
@@ -2,36 +2,50 @@ use ilattice::prelude::Extent;
 
 use crate::prelude::*;
 
-use super::TerrainGenerator;
+use super::{require_block, uniform_from_heights, TerrainGenerator};
 
-#[derive(Default)]
 pub struct FlatTerrainGenerator {
     height: i32,
+    grass: Voxel,
+}
+
+impl FlatTerrainGenerator {
+    pub fn new(registry: &BlockRegistry, height: i32) -> Self {
+        Self {
+            height,
+            grass: require_block(registry, "grass"),
+        }
+    }
 }
 
 impl TerrainGenerator for FlatTerrainGenerator {
-    fn generate_heightmap(&self, _: IVec2) -> Heightmap {
+    fn generate_heightmap(&self, _: ColumnPos) -> Heightmap {
         let mut heightmap = Heightmap::new();
 
-        for (_, height) in heightmap.iter_mut() {
+        for (height, surface) in heightmap.columns_mut() {
             *height = self.height;
+            *surface = self.grass;
         }
 
         heightmap
     }
 
-    fn generate_terrain(&self, origin: IVec3, heightmap: &Heightmap, chunk: &mut VoxelChunk) {
+    fn generate_terrain(&self, origin: ChunkPos, heightmap: &Heightmap, chunk: &mut VoxelChunk) {
         for (position, height) in heightmap.iter() {
             let local_height =
-                (height - (origin.y * CHUNK_SIZE as i32)).clamp(0, PADDED_CHUNK_SIZE as i32);
+                (height - origin.padded_origin().0.y).clamp(0, PADDED_CHUNK_SIZE as i32);
 
             chunk.voxels.fill_extent(
                 Extent::from_min_and_shape(
                     position.extend_y(0),
                     UVec3::new(1, local_height as u32, 1),
                 ),
-                Voxel(3),
+                self.grass,
             );
         }
     }
+
+    fn uniform_chunk(&self, origin: ChunkPos, heightmap: &Heightmap) -> Option<Voxel> {
+        uniform_from_heights(origin, heightmap, self.grass, Voxel::EMPTY)
+    }
 }
@@ -1,9 +1,202 @@
+use std::sync::Arc;
+
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+
 use crate::prelude::*;
 
+use self::{
+    density::{DensityTerrainGenerator, DENSITY_FALLOFF},
+    flat::FlatTerrainGenerator,
+    standard::StandardTerrainGenerator,
+};
+
+use super::{caves::CaveCarver, seed::WorldSeed};
+
+pub mod density;
 pub mod flat;
 pub mod standard;
 
+/// Air below this height is filled with water.
+pub const SEA_LEVEL: i32 = 0;
+
 pub trait TerrainGenerator: Send + Sync {
-    fn generate_heightmap(&self, origin: IVec2) -> Heightmap;
-    fn generate_terrain(&self, origin: IVec3, heightmap: &Heightmap, chunk: &mut VoxelChunk);
+    fn generate_heightmap(&self, origin: ColumnPos) -> Heightmap;
+    /// Fills a chunk from its column's heightmap, covering the terrain with the surface blocks it
+    /// records.
+    fn generate_terrain(&self, origin: ChunkPos, heightmap: &Heightmap, chunk: &mut VoxelChunk);
+    /// Returns the voxel filling the chunk at `origin` if it is known to be uniform, so that
+    /// [`Self::generate_terrain`] can be skipped. This should be cheap, and may return `None` for
+    /// chunks that turn out to be uniform.
+    fn uniform_chunk(&self, _origin: ChunkPos, _heightmap: &Heightmap) -> Option<Voxel> {
+        None
+    }
+}
+
+/// Returns `above` if every column of the heightmap ends below the padded chunk at `origin`, or
+/// `below` if every column reaches above it.
+fn uniform_from_heights(
+    origin: ChunkPos,
+    heightmap: &Heightmap,
+    below: Voxel,
+    above: Voxel,
+) -> Option<Voxel> {
+    let (minimum, maximum) = heightmap.bounds();
+    let bottom = origin.padded_origin().0.y;
+
+    if maximum <= bottom {
+        Some(above)
+    } else if minimum >= bottom + PADDED_CHUNK_SIZE as i32 {
+        Some(below)
+    } else {
+        None
+    }
+}
+
+/// A prebuilt noise graph, shared by every generation task.
+pub type NoiseGraph = Box<dyn NoiseFn<f64, 2> + Send + Sync>;
+
+/// Samples 2D noise for every padded column of a chunk, in the order of [`Heightmap::iter`].
+///
+/// The noise is only evaluated every `spacing` blocks, on a grid aligned to world coordinates,
+/// and interpolated bilinearly in between. Neighbouring chunks share grid points, so the result
+/// is seamless. A spacing of 1 samples every column exactly.
+pub fn sample_columns(noise: &dyn NoiseFn<f64, 2>, origin: ColumnPos, spacing: i32) -> Vec<f64> {
+    let origin = origin.padded_origin();
+    let size = PADDED_CHUNK_SIZE as i32;
+
+    let minimum = IVec2::new(
+        origin.x.div_euclid(spacing) * spacing,
+        origin.y.div_euclid(spacing) * spacing,
+    );
+    let samples = (origin + size - 1 - minimum) / spacing + 2;
+
+    let mut grid = Vec::with_capacity((samples.x * samples.y) as usize);
+    for z in 0..samples.y {
+        for x in 0..samples.x {
+            let position = minimum + IVec2::new(x, z) * spacing;
+            grid.push(noise.get(position.as_dvec2().to_array()));
+        }
+    }
+
+    let mut values = Vec::with_capacity((size * size) as usize);
+    for z in 0..size {
+        for x in 0..size {
+            let relative = origin + IVec2::new(x, z) - minimum;
+            let cell = relative / spacing;
+            let t = (relative % spacing).as_dvec2() / spacing as f64;
+            let sample =
+                |dx: i32, dz: i32| grid[((cell.y + dz) * samples.x + cell.x + dx) as usize];
+
+            let near = sample(0, 0) + (sample(1, 0) - sample(0, 0)) * t.x;
+            let far = sample(0, 1) + (sample(1, 1) - sample(0, 1)) * t.x;
+            values.push(near + (far - near) * t.y);
+        }
+    }
+
+    values
+}
+
+/// Samples 3D noise for every voxel of the box at `minimum` with the given `shape`, with x
+/// varying fastest, then y, then z.
+///
+/// Like [`sample_columns`], the noise is evaluated on a grid aligned to world coordinates, every
+/// `spacing` blocks along each axis, and interpolated trilinearly in between.
+pub fn sample_volume(
+    noise: &dyn NoiseFn<f64, 3>,
+    minimum: IVec3,
+    shape: UVec3,
+    spacing: IVec3,
+) -> Vec<f64> {
+    let shape = shape.as_ivec3();
+
+    let grid_minimum = IVec3::new(
+        minimum.x.div_euclid(spacing.x) * spacing.x,
+        minimum.y.div_euclid(spacing.y) * spacing.y,
+        minimum.z.div_euclid(spacing.z) * spacing.z,
+    );
+    let samples = (minimum + shape - 1 - grid_minimum) / spacing + 2;
+
+    let mut grid = Vec::with_capacity((samples.x * samples.y * samples.z) as usize);
+    for z in 0..samples.z {
+        for y in 0..samples.y {
+            for x in 0..samples.x {
+                let position = grid_minimum + IVec3::new(x, y, z) * spacing;
+                grid.push(noise.get(position.as_dvec3().to_array()));
+            }
+        }
+    }
+
+    let mut values = Vec::with_capacity((shape.x * shape.y * shape.z) as usize);
+    for z in 0..shape.z {
+        for y in 0..shape.y {
+            for x in 0..shape.x {
+                let relative = minimum + IVec3::new(x, y, z) - grid_minimum;
+                let cell = relative / spacing;
+                let t = (relative % spacing).as_dvec3() / spacing.as_dvec3();
+                let sample = |dx: i32, dy: i32, dz: i32| {
+                    let position = cell + IVec3::new(dx, dy, dz);
+                    grid[(position.x + samples.x * (position.y + samples.y * position.z)) as usize]
+                };
+
+                let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+                let bottom = lerp(
+                    lerp(sample(0, 0, 0), sample(1, 0, 0), t.x),
+                    lerp(sample(0, 0, 1), sample(1, 0, 1), t.x),
+                    t.z,
+                );
+                let top = lerp(
+                    lerp(sample(0, 1, 0), sample(1, 1, 0), t.x),
+                    lerp(sample(0, 1, 1), sample(1, 1, 1), t.x),
+                    t.z,
+                );
+                values.push(lerp(bottom, top, t.y));
+            }
+        }
+    }
+
+    values
+}
+
+/// Looks up a block the generator depends on.
+pub fn require_block(registry: &BlockRegistry, name: &str) -> Voxel {
+    registry
+        .id(name)
+        .unwrap_or_else(|| panic!("terrain generation requires a {name:?} block"))
+}
+
+/// Identifies a [`TerrainGenerator`] and its parameters, as recorded in a world's metadata.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerrainGeneratorSettings {
+    #[default]
+    Standard,
+    Flat {
+        height: i32,
+    },
+    /// The standard terrain, reshaped by 3D noise into overhangs, arches and cliffs.
+    Density,
+}
+
+impl TerrainGeneratorSettings {
+    pub fn build(&self, registry: &BlockRegistry, seed: WorldSeed) -> Arc<dyn TerrainGenerator> {
+        match *self {
+            Self::Standard => Arc::new(StandardTerrainGenerator::new(registry, seed)),
+            Self::Flat { height } => Arc::new(FlatTerrainGenerator::new(registry, height)),
+            Self::Density => Arc::new(DensityTerrainGenerator::new(registry, seed)),
+        }
+    }
+
+    /// Builds the cave carving pass for the generator's terrain, if it has caves. Legacy worlds
+    /// have none, so that new chunks match those already saved.
+    pub fn build_caves(&self, registry: &BlockRegistry, seed: WorldSeed) -> Option<CaveCarver> {
+        if seed == WorldSeed::Legacy {
+            return None;
+        }
+
+        match *self {
+            Self::Standard => Some(CaveCarver::new(registry, seed, 0)),
+            Self::Flat { .. } => None,
+            Self::Density => Some(CaveCarver::new(registry, seed, DENSITY_FALLOFF)),
+        }
+    }
 }
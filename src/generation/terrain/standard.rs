@@ -1,85 +1,152 @@
 use ilattice::prelude::Extent;
 use noise::{Clamp, Curve, Fbm, MultiFractal, NoiseFn, OpenSimplex};
 
-use crate::prelude::*;
+use crate::{
+    generation::{biomes::BiomeMap, seed::WorldSeed},
+    prelude::*,
+};
+
+use super::{
+    require_block, sample_columns, uniform_from_heights, NoiseGraph, TerrainGenerator, SEA_LEVEL,
+};
+
+/// The spacing, in blocks, at which the height noise of seeded worlds is sampled.
+const HEIGHT_SAMPLE_SPACING: i32 = 4;
+
+pub struct StandardTerrainGenerator {
+    height: NoiseGraph,
+    height_spacing: i32,
+    /// `None` in legacy worlds, which are covered in grass.
+    biomes: Option<BiomeMap>,
+    stone: Voxel,
+    grass: Voxel,
+    water: Voxel,
+}
 
-use super::TerrainGenerator;
+impl StandardTerrainGenerator {
+    pub fn new(registry: &BlockRegistry, seed: WorldSeed) -> Self {
+        Self {
+            height: Box::new(height_noise(seed.derive("height").noise_seed())),
+            // Legacy worlds were sampled at every column, and new chunks must match saved ones.
+            height_spacing: if seed == WorldSeed::Legacy {
+                1
+            } else {
+                HEIGHT_SAMPLE_SPACING
+            },
+            biomes: (seed != WorldSeed::Legacy).then(|| BiomeMap::new(registry, seed)),
+            stone: require_block(registry, "stone"),
+            grass: require_block(registry, "grass"),
+            water: require_block(registry, "water"),
+        }
+    }
+}
 
-#[derive(Default)]
-pub struct StandardTerrainGenerator;
+fn height_noise(seed: u32) -> impl NoiseFn<f64, 2> + Send + Sync {
+    let simplex = Fbm::<OpenSimplex>::new(seed)
+        .set_octaves(4)
+        .set_frequency(0.005)
+        .set_persistence(0.5)
+        .set_lacunarity(2.0);
+
+    let noise = Curve::new(simplex)
+        .add_control_point(-1.0, 0.0)
+        .add_control_point(-0.8, 0.0)
+        .add_control_point(-0.75, -0.25)
+        .add_control_point(-0.7, 0.0)
+        .add_control_point(0.25, 0.0)
+        .add_control_point(0.5, 0.75)
+        .add_control_point(1.0, 1.0);
+
+    // let rivers_simplex = Fbm::<OpenSimplex>::new(1)
+    //     .set_octaves(1)
+    //     .set_frequency(0.005)
+    //     .set_persistence(0.5)
+    //     .set_lacunarity(2.0);
+
+    // let rivers = Curve::new(rivers_simplex)
+    //     .add_control_point(-1.0, -1.0)
+    //     .add_control_point(-0.05, -1.0)
+    //     .add_control_point(0.05, 0.0)
+    //     .add_control_point(0.05, -1.0)
+    //     .add_control_point(1.0, -1.0);
+
+    // let rivers = Clamp::new(rivers).set_bounds(-1.0, 0.0);
+
+    Clamp::new(noise).set_bounds(-1.0, 1.0)
+}
 
 impl TerrainGenerator for StandardTerrainGenerator {
-    fn generate_heightmap(&self, origin: IVec2) -> Heightmap {
-        let simplex = Fbm::<OpenSimplex>::new(0)
-            .set_octaves(4)
-            .set_frequency(0.005)
-            .set_persistence(0.5)
-            .set_lacunarity(2.0);
-
-        let noise = Curve::new(simplex)
-            .add_control_point(-1.0, 0.0)
-            .add_control_point(-0.8, 0.0)
-            .add_control_point(-0.75, -0.25)
-            .add_control_point(-0.7, 0.0)
-            .add_control_point(0.25, 0.0)
-            .add_control_point(0.5, 0.75)
-            .add_control_point(1.0, 1.0);
-
-        // let rivers_simplex = Fbm::<OpenSimplex>::new(1)
-        //     .set_octaves(1)
-        //     .set_frequency(0.005)
-        //     .set_persistence(0.5)
-        //     .set_lacunarity(2.0);
-
-        // let rivers = Curve::new(rivers_simplex)
-        //     .add_control_point(-1.0, -1.0)
-        //     .add_control_point(-0.05, -1.0)
-        //     .add_control_point(0.05, 0.0)
-        //     .add_control_point(0.05, -1.0)
-        //     .add_control_point(1.0, -1.0);
-
-        let noise = Clamp::new(noise).set_bounds(-1.0, 1.0);
-        // let rivers = Clamp::new(rivers).set_bounds(-1.0, 0.0);
-
+    fn generate_heightmap(&self, origin: ColumnPos) -> Heightmap {
         let mut heightmap = Heightmap::new();
 
-        for (offset, height) in heightmap.iter_mut() {
-            let position = (origin * CHUNK_SIZE as i32) + offset.as_ivec2();
-
-            let dposition = position.as_dvec2().to_array();
-
-            *height = noise.get(dposition).mul_add(100.0, 0.0) as i32;
+        let samples = sample_columns(&*self.height, origin, self.height_spacing);
+
+        match &self.biomes {
+            Some(biomes) => {
+                let weights = biomes.weights(origin);
+                for ((height, surface), (sample, weights)) in heightmap
+                    .columns_mut()
+                    .zip(samples.into_iter().zip(weights))
+                {
+                    *height = biomes.height(&weights, sample) as i32;
+                    *surface = biomes.surface_block(&weights, *height);
+                }
+            }
+            None => {
+                for ((height, surface), sample) in heightmap.columns_mut().zip(samples) {
+                    *height = sample.mul_add(100.0, 0.0) as i32;
+                    *surface = self.grass;
+                }
+            }
         }
 
         heightmap
     }
 
-    fn generate_terrain(&self, origin: IVec3, heightmap: &Heightmap, chunk: &mut VoxelChunk) {
+    fn generate_terrain(&self, origin: ChunkPos, heightmap: &Heightmap, chunk: &mut VoxelChunk) {
+        let bottom = origin.padded_origin().0.y;
+
         for (position, height) in heightmap.iter() {
-            let local_height =
-                (height - (origin.y * CHUNK_SIZE as i32)).clamp(0, PADDED_CHUNK_SIZE as i32);
+            let local_height = (height - bottom).clamp(0, PADDED_CHUNK_SIZE as i32);
 
             chunk.voxels.fill_extent(
                 Extent::from_min_and_shape(
                     position.extend_y(0),
                     UVec3::new(1, local_height as u32, 1),
                 ),
-                Voxel(2),
+                self.stone,
             );
 
             if local_height > 0 && local_height < PADDED_CHUNK_SIZE as i32 {
                 *chunk
                     .voxels
-                    .voxel_at_mut(position.extend_y(local_height as u32)) = Voxel(3);
+                    .voxel_at_mut(position.extend_y(local_height as u32)) =
+                    heightmap.surface(position);
             }
 
-            if height < 0 && local_height > 0 && local_height < PADDED_CHUNK_SIZE as i32 {
+            // Water fills the column from above its surface block up to the sea level.
+            let water_bottom = (height + 1 - bottom).clamp(0, PADDED_CHUNK_SIZE as i32);
+            let water_top = (SEA_LEVEL - bottom).clamp(0, PADDED_CHUNK_SIZE as i32);
+            if water_bottom < water_top {
                 let extent = Extent::from_min_and_shape(
-                    position.extend_y(local_height as u32),
-                    UVec3::new(1, PADDED_CHUNK_SIZE - local_height as u32 - 1, 1),
+                    position.extend_y(water_bottom as u32),
+                    UVec3::new(1, (water_top - water_bottom) as u32, 1),
                 );
-                chunk.voxels.fill_extent(extent, Voxel(1));
+                chunk.voxels.fill_extent(extent, self.water);
             }
         }
     }
+
+    fn uniform_chunk(&self, origin: ChunkPos, heightmap: &Heightmap) -> Option<Voxel> {
+        let (_, maximum) = heightmap.bounds();
+        let bottom = origin.padded_origin().0.y;
+        let top = bottom + PADDED_CHUNK_SIZE as i32;
+
+        // Chunks above the sea floor but below the sea level hold water.
+        if maximum <= bottom && bottom < SEA_LEVEL {
+            return (maximum < bottom && top <= SEA_LEVEL).then_some(self.water);
+        }
+
+        uniform_from_heights(origin, heightmap, self.stone, Voxel::EMPTY)
+    }
 }
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
-use crate::prelude::*;
+use crate::{persistence::metadata::WorldMetadata, prelude::*};
 
 use super::chunk::ChunkGenerator;
 
-#[derive(Default, Resource)]
+#[derive(Resource)]
 pub struct VoxelWorldGenerator {
     chunk_generator: Arc<ChunkGenerator>,
 }
@@ -14,3 +14,19 @@ impl VoxelWorldGenerator {
         self.chunk_generator.clone()
     }
 }
+
+impl FromWorld for VoxelWorldGenerator {
+    fn from_world(world: &mut World) -> Self {
+        let registry = world.resource::<BlockRegistry>();
+        let metadata = world.resource::<WorldMetadata>();
+
+        let seed = metadata.world_seed();
+
+        Self {
+            chunk_generator: Arc::new(ChunkGenerator::new(
+                metadata.generator.build(registry, seed),
+                metadata.generator.build_caves(registry, seed),
+            )),
+        }
+    }
+}
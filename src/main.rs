@@ -1,6 +1,9 @@
 pub mod associated_ord;
 pub mod cache;
+pub mod edit;
+pub mod formats;
 pub mod generation;
+pub mod persistence;
 pub mod player;
 mod prelude;
 pub mod queue;
@@ -11,7 +14,9 @@ mod ui;
 pub mod world;
 
 use bevy::window::PresentMode;
+use edit::EditPlugin;
 use generation::GenerationPlugin;
+use persistence::PersistencePlugin;
 use player::PlayerPlugin;
 use render::RenderPlugin;
 use ui::UiPlugin;
@@ -31,6 +36,8 @@ fn main() {
                 ..default()
             }),
             StoragePlugin,
+            PersistencePlugin,
+            EditPlugin,
             PlayerPlugin,
             GenerationPlugin,
             RenderPlugin,
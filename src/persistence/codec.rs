@@ -0,0 +1,206 @@
+use std::io::{self, Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::prelude::*;
+
+/// Chunks stored as a flat array of padded voxels.
+const DENSE_FORMAT_VERSION: u8 = 1;
+/// Chunks stored as a palette followed by bit-packed palette indices.
+const PALETTE_FORMAT_VERSION: u8 = 2;
+
+/// Serializes a chunk, including its padding, into a compressed byte buffer.
+pub fn encode_chunk(chunk: &VoxelChunk) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(vec![PALETTE_FORMAT_VERSION], Compression::fast());
+
+    let palette = chunk.voxels.palette();
+    encoder.write_all(&(palette.len() as u32).to_le_bytes())?;
+    for voxel in palette {
+        encoder.write_all(&voxel.0.to_le_bytes())?;
+    }
+
+    encoder.write_all(&[chunk.voxels.bits_per_index() as u8])?;
+    for word in chunk.voxels.words() {
+        encoder.write_all(&word.to_le_bytes())?;
+    }
+
+    encoder.finish()
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> io::Result<VoxelChunk> {
+    let Some((&version, data)) = bytes.split_first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "empty chunk data",
+        ));
+    };
+
+    let mut decoder = ZlibDecoder::new(data);
+
+    let result = match version {
+        DENSE_FORMAT_VERSION => decode_dense(&mut decoder),
+        PALETTE_FORMAT_VERSION => decode_palette(&mut decoder),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported chunk format version {version}"),
+        )),
+    };
+
+    // The decoder reports a corrupt stream as invalid input.
+    result.map_err(|err| match err.kind() {
+        io::ErrorKind::InvalidInput => io::Error::new(io::ErrorKind::InvalidData, err),
+        _ => err,
+    })
+}
+
+fn decode_dense(reader: &mut impl Read) -> io::Result<VoxelChunk> {
+    let mut raw = Vec::with_capacity(PADDED_CHUNK_SIZE.pow(3) as usize * 2);
+    reader.read_to_end(&mut raw)?;
+
+    if raw.len() != PADDED_CHUNK_SIZE.pow(3) as usize * 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("chunk data has unexpected length {}", raw.len()),
+        ));
+    }
+
+    let mut chunk = VoxelChunk::default();
+
+    for (index, bytes) in raw.chunks_exact(2).enumerate() {
+        chunk
+            .voxels
+            .set_voxel_at_index(index, Voxel(u16::from_le_bytes([bytes[0], bytes[1]])));
+    }
+
+    Ok(chunk)
+}
+
+fn decode_palette(reader: &mut impl Read) -> io::Result<VoxelChunk> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+
+    if len > u16::MAX as usize + 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("chunk palette has unexpected length {len}"),
+        ));
+    }
+
+    let mut palette = Vec::with_capacity(len);
+    for _ in 0..len {
+        let mut voxel = [0; 2];
+        reader.read_exact(&mut voxel)?;
+        palette.push(Voxel(u16::from_le_bytes(voxel)));
+    }
+
+    let mut bits = [0; 1];
+    reader.read_exact(&mut bits)?;
+
+    let mut raw = Vec::new();
+    reader.read_to_end(&mut raw)?;
+
+    if raw.len() % 8 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "chunk indices are not word aligned",
+        ));
+    }
+
+    let words = raw
+        .chunks_exact(8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+
+    let voxels =
+        PaletteBuffer::from_raw_parts(palette, bits[0] as u32, words).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk palette data is inconsistent",
+            )
+        })?;
+
+    Ok(VoxelChunk { voxels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_chunk() -> VoxelChunk {
+        let mut chunk = VoxelChunk::default();
+        for index in (0..PADDED_CHUNK_SIZE.pow(3) as usize).step_by(37) {
+            chunk
+                .voxels
+                .set_voxel_at_index(index, Voxel((index % 20) as u16));
+        }
+
+        chunk
+    }
+
+    fn assert_same(a: &VoxelChunk, b: &VoxelChunk) {
+        for index in 0..PADDED_CHUNK_SIZE.pow(3) as usize {
+            assert_eq!(
+                a.voxels.voxel_at_index(index),
+                b.voxels.voxel_at_index(index)
+            );
+        }
+    }
+
+    #[test]
+    fn palette_round_trip() {
+        let chunk = test_chunk();
+        assert_same(
+            &decode_chunk(&encode_chunk(&chunk).unwrap()).unwrap(),
+            &chunk,
+        );
+
+        let uniform = VoxelChunk::uniform(Voxel(4));
+        let decoded = decode_chunk(&encode_chunk(&uniform).unwrap()).unwrap();
+        assert_eq!(decoded.as_uniform(), Some(Voxel(4)));
+    }
+
+    #[test]
+    fn decode_dense() {
+        let chunk = test_chunk();
+
+        let mut encoder = ZlibEncoder::new(vec![DENSE_FORMAT_VERSION], Compression::fast());
+        for index in 0..PADDED_CHUNK_SIZE.pow(3) as usize {
+            let voxel = chunk.voxels.voxel_at_index(index);
+            encoder.write_all(&voxel.0.to_le_bytes()).unwrap();
+        }
+        let bytes = encoder.finish().unwrap();
+
+        assert_same(&decode_chunk(&bytes).unwrap(), &chunk);
+    }
+
+    #[test]
+    fn corrupt_chunks() {
+        let bytes = encode_chunk(&test_chunk()).unwrap();
+
+        for corrupt in [
+            &[][..],
+            &[0][..],
+            &[PALETTE_FORMAT_VERSION, 1, 2, 3][..],
+            &bytes[..bytes.len() / 2],
+        ] {
+            let err = decode_chunk(corrupt).err().unwrap();
+            assert!(matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ));
+        }
+
+        // A palette of 3 voxels with index 3 in the first word.
+        let mut encoder = ZlibEncoder::new(vec![PALETTE_FORMAT_VERSION], Compression::fast());
+        encoder.write_all(&3u32.to_le_bytes()).unwrap();
+        encoder.write_all(&[0; 6]).unwrap();
+        encoder.write_all(&[2]).unwrap();
+        encoder.write_all(&3u64.to_le_bytes()).unwrap();
+        let words = (PADDED_CHUNK_SIZE.pow(3) as usize).div_ceil(32);
+        encoder.write_all(&vec![0; (words - 1) * 8]).unwrap();
+
+        let err = decode_chunk(&encoder.finish().unwrap()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generation::{
+        seed::{splitmix64, WorldSeed},
+        terrain::TerrainGeneratorSettings,
+    },
+    prelude::*,
+};
+
+use super::{region::upgrade_region_file, unix_time, REGIONS_DIRECTORY};
+
+/// The name of the metadata file within a world directory.
+pub const METADATA_FILE: &str = "world.ron";
+
+/// The storage format version written by this build. Bump it and append to [`MIGRATIONS`]
+/// whenever the layout of a world directory changes.
+pub const WORLD_FORMAT_VERSION: u32 = 3;
+
+/// Upgrades a world directory from one format version to the next.
+type Migration = fn(&Path, &mut WorldMetadata) -> io::Result<()>;
+
+/// Migrations indexed by the format version they upgrade from.
+const MIGRATIONS: [Migration; WORLD_FORMAT_VERSION as usize] = [migrate_v0, migrate_v1, migrate_v2];
+
+/// The settings a world was created with, stored alongside its chunks.
+#[derive(Clone, Debug, Serialize, Deserialize, Resource)]
+pub struct WorldMetadata {
+    pub format_version: u32,
+    pub seed: u64,
+    pub generator: TerrainGeneratorSettings,
+    pub spawn: [i32; 3],
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// Set for worlds created before terrain generation used the seed, whose terrain is
+    /// generated with [`WorldSeed::Legacy`] so that new chunks match those already saved.
+    #[serde(default)]
+    pub legacy_seed: bool,
+}
+
+impl WorldMetadata {
+    pub fn new(seed: u64, generator: TerrainGeneratorSettings) -> Self {
+        Self {
+            format_version: WORLD_FORMAT_VERSION,
+            seed,
+            generator,
+            spawn: [2, 5, 2],
+            created: unix_time(),
+            legacy_seed: false,
+        }
+    }
+
+    /// Opens the world in `directory`, creating it with a random seed if it does not exist and
+    /// migrating it if it was written by an older version.
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref();
+
+        let mut metadata = match fs::read_to_string(metadata_path(directory)) {
+            Ok(source) => ron::from_str(&source)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if directory.join(REGIONS_DIRECTORY).exists() {
+                    Self::legacy()
+                } else {
+                    info!("creating world in {}", directory.display());
+                    Self::new(random_seed(), TerrainGeneratorSettings::default())
+                }
+            }
+            Err(err) => return Err(err),
+        };
+
+        if metadata.format_version > WORLD_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "world format version {} is newer than the supported version {}",
+                    metadata.format_version, WORLD_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        while metadata.format_version < WORLD_FORMAT_VERSION {
+            info!(
+                "migrating world from format version {}",
+                metadata.format_version
+            );
+            MIGRATIONS[metadata.format_version as usize](directory, &mut metadata)?;
+            metadata.format_version += 1;
+        }
+
+        metadata.save(directory)?;
+
+        Ok(metadata)
+    }
+
+    /// Writes the metadata file, replacing it atomically.
+    pub fn save(&self, directory: impl AsRef<Path>) -> io::Result<()> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let path = metadata_path(directory);
+        let temp_path = path.with_extension("ron.tmp");
+        fs::write(&temp_path, source)?;
+        fs::rename(temp_path, path)
+    }
+
+    /// Returns the seed terrain generation draws from.
+    pub fn world_seed(&self) -> WorldSeed {
+        if self.legacy_seed {
+            WorldSeed::Legacy
+        } else {
+            WorldSeed::Seeded(self.seed)
+        }
+    }
+
+    #[inline]
+    pub fn spawn(&self) -> Vec3 {
+        IVec3::from_array(self.spawn).as_vec3()
+    }
+
+    /// The metadata of a world saved before metadata files existed.
+    fn legacy() -> Self {
+        Self {
+            format_version: 0,
+            ..Self::new(0, TerrainGeneratorSettings::Standard)
+        }
+    }
+}
+
+fn metadata_path(directory: &Path) -> PathBuf {
+    directory.join(METADATA_FILE)
+}
+
+/// Version 0 worlds have no metadata file. They were generated by the standard generator with
+/// seed 0, as [`WorldMetadata::legacy`] records, and their regions are already in the version 1
+/// layout, so nothing on disk changes.
+fn migrate_v0(_: &Path, _: &mut WorldMetadata) -> io::Result<()> {
+    Ok(())
+}
+
+/// Version 2 adds a checksum to every chunk stored in a region file.
+fn migrate_v1(directory: &Path, _: &mut WorldMetadata) -> io::Result<()> {
+    let regions = directory.join(REGIONS_DIRECTORY);
+    if !regions.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(regions)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "vxr") {
+            upgrade_region_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 3 generates terrain from the world seed. Older worlds ignored it, so they keep
+/// generating from [`WorldSeed::Legacy`].
+fn migrate_v2(_: &Path, metadata: &mut WorldMetadata) -> io::Result<()> {
+    metadata.legacy_seed = true;
+    Ok(())
+}
+
+/// Derives a seed from the current time, mixed so that worlds created close together differ.
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+
+    splitmix64(nanos)
+}
@@ -0,0 +1,60 @@
+pub mod codec;
+pub mod metadata;
+pub mod region;
+pub mod save;
+
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::prelude::*;
+
+use self::{metadata::WorldMetadata, region::RegionStorage, save::SaveChunkPlugin};
+
+/// The directory the world is saved in.
+pub const WORLD_PATH: &str = "saves/world";
+/// The directory within a world that region files are stored in.
+pub const REGIONS_DIRECTORY: &str = "regions";
+
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        let metadata = WorldMetadata::open(WORLD_PATH)
+            .unwrap_or_else(|err| panic!("failed to open world {WORLD_PATH}: {err}"));
+
+        app.insert_resource(metadata)
+            .init_resource::<ChunkStorage>()
+            .add_plugins(SaveChunkPlugin);
+    }
+}
+
+#[derive(Resource)]
+pub struct ChunkStorage {
+    region_storage: Arc<RegionStorage>,
+}
+
+impl Default for ChunkStorage {
+    fn default() -> Self {
+        Self {
+            region_storage: Arc::new(RegionStorage::new(
+                Path::new(WORLD_PATH).join(REGIONS_DIRECTORY),
+            )),
+        }
+    }
+}
+
+impl ChunkStorage {
+    pub fn get(&self) -> Arc<RegionStorage> {
+        self.region_storage.clone()
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,826 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+use super::{
+    codec::{decode_chunk, encode_chunk},
+    unix_time,
+};
+
+/// The number of chunks along each axis of a region.
+pub const REGION_SIZE: i32 = 8;
+pub const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const REGION_MAGIC: [u8; 4] = *b"VXRG";
+/// Version 1 records have no checksum.
+const LEGACY_REGION_FORMAT_VERSION: u32 = 1;
+const REGION_FORMAT_VERSION: u32 = 2;
+
+const JOURNAL_MAGIC: [u8; 4] = *b"VXJL";
+/// Marks the end of a complete journal in place of a chunk index.
+const JOURNAL_COMMIT: u32 = u32::MAX;
+
+const SECTOR_SIZE: u64 = 4096;
+const HEADER_SIZE: u64 = 8 + REGION_VOLUME as u64 * 8;
+const HEADER_SECTORS: u32 = HEADER_SIZE.div_ceil(SECTOR_SIZE) as u32;
+/// The size of the length and checksum preceding each chunk.
+const RECORD_HEADER_SIZE: u64 = 8;
+
+/// The maximum number of region files kept open at once.
+const MAX_OPEN_REGIONS: usize = 64;
+
+/// The directory within the region directory that corrupted data is moved to.
+const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+/// Returns the region containing the chunk and the chunk's index within it.
+pub fn region_of(position: ChunkPos) -> (IVec3, usize) {
+    let region = IVec3::new(
+        position.0.x.div_euclid(REGION_SIZE),
+        position.0.y.div_euclid(REGION_SIZE),
+        position.0.z.div_euclid(REGION_SIZE),
+    );
+    let local = position.0 - region * REGION_SIZE;
+    let index = (local.x + REGION_SIZE * (local.y + REGION_SIZE * local.z)) as usize;
+
+    (region, index)
+}
+
+/// Returns whether an error means that stored data is damaged rather than inaccessible.
+pub fn is_corruption(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+    )
+}
+
+#[derive(Clone, Copy, Default)]
+struct RegionEntry {
+    /// The first sector of the chunk, or 0 if the chunk is not stored.
+    offset: u32,
+    sectors: u32,
+}
+
+/// A file storing up to [`REGION_VOLUME`] chunks in fixed-size sectors.
+///
+/// The file starts with a header mapping each chunk to a run of sectors. Each run holds the
+/// length of the encoded chunk, its CRC-32 checksum and the encoded chunk itself.
+///
+/// Writes go through a journal next to the region file. The chunks are written to the journal
+/// and synced before the region file is touched, and the journal is removed once the region file
+/// is synced, so a crash mid-save either loses the whole batch or is completed by replaying the
+/// journal the next time the region is opened.
+pub struct RegionFile {
+    file: File,
+    journal_path: PathBuf,
+    entries: Box<[RegionEntry]>,
+    used_sectors: Vec<bool>,
+}
+
+impl RegionFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let mut entries = if file.metadata()?.len() == 0 {
+            let mut header = Vec::with_capacity((HEADER_SECTORS as u64 * SECTOR_SIZE) as usize);
+            header.extend_from_slice(&REGION_MAGIC);
+            header.extend_from_slice(&REGION_FORMAT_VERSION.to_le_bytes());
+            header.resize((HEADER_SECTORS as u64 * SECTOR_SIZE) as usize, 0);
+            file.write_all(&header)?;
+            file.sync_data()?;
+
+            vec![RegionEntry::default(); REGION_VOLUME].into_boxed_slice()
+        } else {
+            read_header(&mut file, REGION_FORMAT_VERSION)?
+        };
+
+        let total_sectors = file.metadata()?.len().div_ceil(SECTOR_SIZE) as usize;
+        let mut used_sectors = vec![false; total_sectors.max(HEADER_SECTORS as usize)];
+        used_sectors[..HEADER_SECTORS as usize].fill(true);
+
+        for (index, entry) in entries.iter_mut().enumerate() {
+            if entry.offset == 0 {
+                continue;
+            }
+
+            // Records are always written in full before the header points to them, so an entry
+            // outside the file is damage. Dropping it lets the chunk be regenerated.
+            let range = entry.offset as usize..entry.offset as usize + entry.sectors as usize;
+            if range.start < HEADER_SECTORS as usize || range.end > total_sectors {
+                warn!(
+                    "dropping invalid entry for chunk {index} in {}",
+                    path.display()
+                );
+                *entry = RegionEntry::default();
+                continue;
+            }
+
+            used_sectors[range].fill(true);
+        }
+
+        let mut region = Self {
+            file,
+            journal_path: journal_path(path),
+            entries,
+            used_sectors,
+        };
+        region.recover()?;
+
+        Ok(region)
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.entries[index].offset != 0
+    }
+
+    /// Reads a chunk, verifying its checksum.
+    pub fn read(&mut self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.entries[index];
+
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+
+        self.file
+            .seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE))?;
+
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.file.read_exact(&mut header)?;
+        let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+        if length + RECORD_HEADER_SIZE > entry.sectors as u64 * SECTOR_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk length exceeds its allocated sectors",
+            ));
+        }
+
+        let mut data = vec![0; length as usize];
+        self.file.read_exact(&mut data)?;
+
+        if crc32fast::hash(&data) != checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk checksum mismatch",
+            ));
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Reads the sectors allocated to a chunk without interpreting them, stopping at the end of
+    /// the file.
+    pub fn read_raw(&mut self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.entries[index];
+
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+
+        self.file
+            .seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE))?;
+
+        let mut data = Vec::new();
+        (&mut self.file)
+            .take(entry.sectors as u64 * SECTOR_SIZE)
+            .read_to_end(&mut data)?;
+
+        Ok(Some(data))
+    }
+
+    pub fn write(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        self.write_batch(&[(index, data)])
+    }
+
+    /// Writes several chunks, journaling them so that either all or none are stored if the
+    /// process is interrupted.
+    pub fn write_batch(&mut self, chunks: &[(usize, &[u8])]) -> io::Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        let mut journal_file = File::create(&self.journal_path)?;
+        journal_file.write_all(&encode_journal(chunks))?;
+        journal_file.sync_data()?;
+        drop(journal_file);
+
+        self.apply(chunks)?;
+        fs::remove_file(&self.journal_path)
+    }
+
+    /// Removes a chunk from the region.
+    pub fn remove(&mut self, index: usize) -> io::Result<()> {
+        let old = self.entries[index];
+        if old.offset == 0 {
+            return Ok(());
+        }
+
+        self.set_entry(index, RegionEntry::default())?;
+        self.file.sync_data()?;
+        self.free(old);
+
+        Ok(())
+    }
+
+    /// Writes chunks to the region file and syncs it.
+    fn apply(&mut self, chunks: &[(usize, &[u8])]) -> io::Result<()> {
+        for (index, data) in chunks {
+            if *index >= REGION_VOLUME {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("chunk index {index} is outside the region"),
+                ));
+            }
+
+            self.write_record(*index, data)?;
+        }
+
+        self.file.sync_data()
+    }
+
+    fn write_record(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        let old = self.entries[index];
+        self.free(old);
+
+        let sectors = (data.len() as u64 + RECORD_HEADER_SIZE).div_ceil(SECTOR_SIZE) as u32;
+        let offset = self.allocate(sectors);
+
+        let mut record = Vec::with_capacity((sectors as u64 * SECTOR_SIZE) as usize);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        record.extend_from_slice(data);
+        record.resize((sectors as u64 * SECTOR_SIZE) as usize, 0);
+
+        self.file
+            .seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE))?;
+        self.file.write_all(&record)?;
+
+        self.set_entry(index, RegionEntry { offset, sectors })
+    }
+
+    /// Replays the journal left by an interrupted write. A journal that was not completely
+    /// written is discarded, as the region file was not modified yet.
+    fn recover(&mut self) -> io::Result<()> {
+        let journal = match fs::read(&self.journal_path) {
+            Ok(journal) => journal,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        match parse_journal(&journal) {
+            Some(chunks) => {
+                warn!(
+                    "replaying {} chunks from {}",
+                    chunks.len(),
+                    self.journal_path.display()
+                );
+                self.apply(&chunks)?;
+            }
+            None => warn!(
+                "discarding incomplete journal {}",
+                self.journal_path.display()
+            ),
+        }
+
+        fs::remove_file(&self.journal_path)
+    }
+
+    fn free(&mut self, entry: RegionEntry) {
+        if entry.offset != 0 {
+            self.used_sectors[entry.offset as usize..(entry.offset + entry.sectors) as usize]
+                .fill(false);
+        }
+    }
+
+    /// Finds the first run of free sectors large enough to hold `sectors`, growing the file if
+    /// there is none, and marks it as used.
+    fn allocate(&mut self, sectors: u32) -> u32 {
+        let sectors = sectors as usize;
+        let mut start = HEADER_SECTORS as usize;
+
+        while start < self.used_sectors.len() {
+            match self.used_sectors[start..]
+                .iter()
+                .take(sectors)
+                .position(|used| *used)
+            {
+                Some(used) => start += used + 1,
+                None => break,
+            }
+        }
+
+        if start + sectors > self.used_sectors.len() {
+            self.used_sectors.resize(start + sectors, false);
+        }
+        self.used_sectors[start..start + sectors].fill(true);
+
+        start as u32
+    }
+
+    fn set_entry(&mut self, index: usize, entry: RegionEntry) -> io::Result<()> {
+        let mut bytes = [0; 8];
+        bytes[0..4].copy_from_slice(&entry.offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&entry.sectors.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(8 + index as u64 * 8))?;
+        self.file.write_all(&bytes)?;
+        self.entries[index] = entry;
+
+        Ok(())
+    }
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+/// Reads the header of a region file, checking its magic and version.
+fn read_header(file: &mut File, version: u32) -> io::Result<Box<[RegionEntry]>> {
+    let mut header = vec![0; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+
+    if header[0..4] != REGION_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "region file has an invalid header",
+        ));
+    }
+
+    let found = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if found != version {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported region format version {found}"),
+        ));
+    }
+
+    let mut entries = vec![RegionEntry::default(); REGION_VOLUME].into_boxed_slice();
+    for (entry, bytes) in entries.iter_mut().zip(header[8..].chunks_exact(8)) {
+        entry.offset = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        entry.sectors = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    }
+
+    Ok(entries)
+}
+
+fn encode_journal(chunks: &[(usize, &[u8])]) -> Vec<u8> {
+    let mut journal = Vec::new();
+    journal.extend_from_slice(&JOURNAL_MAGIC);
+    for (index, data) in chunks {
+        journal.extend_from_slice(&(*index as u32).to_le_bytes());
+        journal.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        journal.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        journal.extend_from_slice(data);
+    }
+    journal.extend_from_slice(&JOURNAL_COMMIT.to_le_bytes());
+    journal.extend_from_slice(&crc32fast::hash(&journal).to_le_bytes());
+
+    journal
+}
+
+/// Parses a journal, returning `None` unless it is complete and every checksum matches.
+fn parse_journal(journal: &[u8]) -> Option<Vec<(usize, &[u8])>> {
+    let (body, checksum) = journal.split_at(journal.len().checked_sub(4)?);
+    if crc32fast::hash(body) != u32::from_le_bytes(checksum.try_into().ok()?) {
+        return None;
+    }
+
+    let mut reader = body.strip_prefix(&JOURNAL_MAGIC)?;
+    let mut chunks = Vec::new();
+
+    loop {
+        let index = u32::from_le_bytes(reader.get(0..4)?.try_into().ok()?);
+        if index == JOURNAL_COMMIT {
+            return (reader.len() == 4).then_some(chunks);
+        }
+
+        let length = u32::from_le_bytes(reader.get(4..8)?.try_into().ok()?) as usize;
+        let checksum = u32::from_le_bytes(reader.get(8..12)?.try_into().ok()?);
+        let data = reader.get(12..12 + length)?;
+
+        if index as usize >= REGION_VOLUME || crc32fast::hash(data) != checksum {
+            return None;
+        }
+
+        chunks.push((index as usize, data));
+        reader = &reader[12 + length..];
+    }
+}
+
+/// Rewrites a version 1 region file in the current format, adding a checksum to every chunk.
+///
+/// Files already in the current format are left alone, so an interrupted upgrade can be run
+/// again. A file whose header cannot be read is moved to the quarantine directory, and damaged
+/// chunks are dropped so that they are regenerated.
+pub fn upgrade_region_file(path: &Path) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    if read_header(&mut file, REGION_FORMAT_VERSION).is_ok() {
+        return Ok(());
+    }
+
+    let entries = match read_header(&mut file, LEGACY_REGION_FORMAT_VERSION) {
+        Err(err) if is_corruption(&err) => {
+            warn!(
+                "region file {} is corrupt, quarantining it: {err}",
+                path.display()
+            );
+            drop(file);
+            return quarantine_file(path);
+        }
+        entries => entries?,
+    };
+
+    let mut chunks = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.offset == 0 {
+            continue;
+        }
+
+        match read_legacy_chunk(&mut file, *entry) {
+            Ok(Some(data)) => chunks.push((index, data)),
+            Err(err) if !is_corruption(&err) => return Err(err),
+            _ => warn!("dropping damaged chunk {index} from {}", path.display()),
+        }
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    if temp_path.exists() {
+        fs::remove_file(&temp_path)?;
+    }
+
+    let chunks: Vec<_> = chunks
+        .iter()
+        .map(|(index, data)| (*index, &data[..]))
+        .collect();
+    RegionFile::open(&temp_path)?.write_batch(&chunks)?;
+
+    fs::rename(temp_path, path)
+}
+
+/// Reads a chunk from a version 1 region file, returning `None` if its length does not fit in its
+/// sectors.
+fn read_legacy_chunk(file: &mut File, entry: RegionEntry) -> io::Result<Option<Vec<u8>>> {
+    file.seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE))?;
+
+    let mut length = [0; 4];
+    file.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as u64;
+
+    if length + 4 > entry.sectors as u64 * SECTOR_SIZE {
+        return Ok(None);
+    }
+
+    let mut data = vec![0; length as usize];
+    file.read_exact(&mut data)?;
+
+    Ok(Some(data))
+}
+
+/// Moves a damaged region file to the quarantine directory next to it.
+fn quarantine_file(path: &Path) -> io::Result<()> {
+    let directory = path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(QUARANTINE_DIRECTORY);
+    fs::create_dir_all(&directory)?;
+
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{}", unix_time()));
+    fs::rename(path, directory.join(name))?;
+
+    let journal = journal_path(path);
+    if journal.exists() {
+        fs::remove_file(journal)?;
+    }
+
+    Ok(())
+}
+
+/// Stores chunks on disk, grouped into region files of [`REGION_SIZE`]³ chunks.
+pub struct RegionStorage {
+    directory: PathBuf,
+    regions: Mutex<HashMap<IVec3, RegionFile>>,
+}
+
+impl RegionStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            regions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Loads a chunk. A chunk that fails its checksum or cannot be decoded is moved to the
+    /// quarantine directory and an error is returned, so it can be regenerated.
+    pub fn load_chunk(&self, position: ChunkPos) -> io::Result<Option<VoxelChunk>> {
+        let (region, index) = region_of(position);
+
+        let result = self
+            .with_region(region, false, |file| file.read(index))
+            .and_then(|data| data.flatten().map(|data| decode_chunk(&data)).transpose());
+
+        if let Err(err) = &result {
+            if is_corruption(err) {
+                if let Err(quarantine_err) = self.quarantine_chunk(position) {
+                    error!("failed to quarantine chunk {position}: {quarantine_err}");
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn save_chunk(&self, position: ChunkPos, chunk: &VoxelChunk) -> io::Result<()> {
+        let (region, index) = region_of(position);
+        let data = encode_chunk(chunk)?;
+
+        self.with_region(region, true, |file| file.write(index, &data))?;
+
+        Ok(())
+    }
+
+    /// Saves several chunks, writing each region they belong to in a single journaled batch.
+    /// Each region is saved even if an earlier one fails, and the first error is returned.
+    pub fn save_chunks<'a>(
+        &self,
+        chunks: impl IntoIterator<Item = (ChunkPos, &'a VoxelChunk)>,
+    ) -> io::Result<()> {
+        let mut regions: HashMap<IVec3, Vec<(usize, Vec<u8>)>> = HashMap::new();
+
+        for (position, chunk) in chunks {
+            let (region, index) = region_of(position);
+            regions
+                .entry(region)
+                .or_default()
+                .push((index, encode_chunk(chunk)?));
+        }
+
+        // Keep saving the other regions if one fails, reporting the first error.
+        let mut result = Ok(());
+
+        for (region, chunks) in regions {
+            let chunks: Vec<_> = chunks
+                .iter()
+                .map(|(index, data)| (*index, &data[..]))
+                .collect();
+
+            let written = self.with_region(region, true, |file| file.write_batch(&chunks));
+            result = result.and(written.map(|_| ()));
+        }
+
+        result
+    }
+
+    pub fn contains_chunk(&self, position: ChunkPos) -> io::Result<bool> {
+        let (region, index) = region_of(position);
+
+        let contains = self.with_region(region, false, |file| Ok(file.contains(index)))?;
+
+        Ok(contains.unwrap_or(false))
+    }
+
+    /// Copies the stored bytes of a chunk to the quarantine directory and removes the chunk from
+    /// its region.
+    pub fn quarantine_chunk(&self, position: ChunkPos) -> io::Result<()> {
+        let (region, index) = region_of(position);
+
+        self.with_region(region, false, |file| {
+            if let Some(data) = file.read_raw(index)? {
+                let name = format!(
+                    "c.{}.{}.{}.{}.bin",
+                    position.0.x,
+                    position.0.y,
+                    position.0.z,
+                    unix_time()
+                );
+                let directory = self.directory.join(QUARANTINE_DIRECTORY);
+                fs::create_dir_all(&directory)?;
+                fs::write(directory.join(name), data)?;
+            }
+
+            file.remove(index)
+        })?;
+
+        Ok(())
+    }
+
+    fn region_path(&self, region: IVec3) -> PathBuf {
+        self.directory
+            .join(format!("r.{}.{}.{}.vxr", region.x, region.y, region.z))
+    }
+
+    /// Runs `f` on the region file, opening it if necessary. Returns `None` if the region does
+    /// not exist on disk and `create` is false.
+    fn with_region<R>(
+        &self,
+        region: IVec3,
+        create: bool,
+        f: impl FnOnce(&mut RegionFile) -> io::Result<R>,
+    ) -> io::Result<Option<R>> {
+        let mut regions = self.regions.lock().unwrap();
+
+        if !regions.contains_key(&region) {
+            let path = self.region_path(region);
+
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+
+            fs::create_dir_all(&self.directory)?;
+
+            if regions.len() >= MAX_OPEN_REGIONS {
+                if let Some(evicted) = regions.keys().next().copied() {
+                    regions.remove(&evicted);
+                }
+            }
+
+            let file = match RegionFile::open(&path) {
+                Err(err) if is_corruption(&err) => {
+                    warn!(
+                        "region file {} is corrupt, quarantining it: {err}",
+                        path.display()
+                    );
+                    quarantine_file(&path)?;
+                    RegionFile::open(&path)?
+                }
+                file => file?,
+            };
+
+            regions.insert(region, file);
+        }
+
+        f(regions.get_mut(&region).unwrap()).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for a test.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("voxel-region-{name}-{}", std::process::id()));
+        if directory.exists() {
+            fs::remove_dir_all(&directory).unwrap();
+        }
+        fs::create_dir_all(&directory).unwrap();
+
+        directory
+    }
+
+    fn test_chunk() -> VoxelChunk {
+        let mut chunk = VoxelChunk::default();
+        for index in (0..PADDED_CHUNK_SIZE.pow(3) as usize).step_by(101) {
+            chunk
+                .voxels
+                .set_voxel_at_index(index, Voxel((index % 9) as u16));
+        }
+
+        chunk
+    }
+
+    fn assert_same(a: &VoxelChunk, b: &VoxelChunk) {
+        for index in 0..PADDED_CHUNK_SIZE.pow(3) as usize {
+            assert_eq!(
+                a.voxels.voxel_at_index(index),
+                b.voxels.voxel_at_index(index)
+            );
+        }
+    }
+
+    fn quarantined(directory: &Path) -> Vec<String> {
+        fs::read_dir(directory.join(QUARANTINE_DIRECTORY))
+            .map(|entries| {
+                entries
+                    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn journal_is_replayed() {
+        let path = test_directory("replay").join("r.0.0.0.vxr");
+
+        RegionFile::open(&path).unwrap().write(1, b"old").unwrap();
+        // A crash after the journal was synced but before the region file was.
+        fs::write(
+            journal_path(&path),
+            encode_journal(&[(1, b"new"), (2, b"other")]),
+        )
+        .unwrap();
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read(1).unwrap().unwrap(), b"new");
+        assert_eq!(region.read(2).unwrap().unwrap(), b"other");
+        assert!(!journal_path(&path).exists());
+    }
+
+    #[test]
+    fn torn_journal_is_discarded() {
+        let path = test_directory("torn").join("r.0.0.0.vxr");
+
+        RegionFile::open(&path).unwrap().write(1, b"old").unwrap();
+        // A crash while the journal was being written, before the region file was touched.
+        let journal = encode_journal(&[(1, b"new"), (2, b"other")]);
+        fs::write(journal_path(&path), &journal[..journal.len() - 6]).unwrap();
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read(1).unwrap().unwrap(), b"old");
+        assert_eq!(region.read(2).unwrap(), None);
+        assert!(!journal_path(&path).exists());
+    }
+
+    #[test]
+    fn bad_checksum_is_quarantined() {
+        let directory = test_directory("checksum");
+        let position = ChunkPos(IVec3::new(1, 2, 3));
+
+        RegionStorage::new(&directory)
+            .save_chunk(position, &test_chunk())
+            .unwrap();
+
+        // Flip a byte of the stored chunk, after its length and checksum.
+        let path = directory.join("r.0.0.0.vxr");
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[(HEADER_SECTORS as u64 * SECTOR_SIZE + RECORD_HEADER_SIZE) as usize] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        let storage = RegionStorage::new(&directory);
+        let err = storage.load_chunk(position).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(quarantined(&directory).len(), 1);
+
+        // The chunk is gone, so it is generated again.
+        assert!(storage.load_chunk(position).unwrap().is_none());
+    }
+
+    #[test]
+    fn legacy_regions_are_upgraded() {
+        let directory = test_directory("upgrade");
+        let path = directory.join("r.0.0.0.vxr");
+        let chunk = test_chunk();
+        let data = encode_chunk(&chunk).unwrap();
+
+        // A version 1 file with one chunk and one entry whose length overruns its sector.
+        let mut bytes = vec![0; (HEADER_SECTORS as u64 * SECTOR_SIZE) as usize];
+        bytes[0..4].copy_from_slice(&REGION_MAGIC);
+        bytes[4..8].copy_from_slice(&LEGACY_REGION_FORMAT_VERSION.to_le_bytes());
+        for (index, offset) in [(1, HEADER_SECTORS), (2, HEADER_SECTORS + 1)] {
+            let entry = 8 + index * 8;
+            bytes[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            bytes[entry + 4..entry + 8].copy_from_slice(&1u32.to_le_bytes());
+        }
+        let mut record = (data.len() as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(&data);
+        record.resize(SECTOR_SIZE as usize, 0);
+        bytes.extend_from_slice(&record);
+        bytes.extend_from_slice(&(SECTOR_SIZE as u32).to_le_bytes());
+        bytes.resize(bytes.len() + SECTOR_SIZE as usize - 4, 0);
+        fs::write(&path, bytes).unwrap();
+
+        upgrade_region_file(&path).unwrap();
+        let upgraded = fs::read(&path).unwrap();
+
+        // Upgrading again leaves the file alone.
+        upgrade_region_file(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), upgraded);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_same(
+            &decode_chunk(&region.read(1).unwrap().unwrap()).unwrap(),
+            &chunk,
+        );
+        assert!(!region.contains(2));
+    }
+
+    #[test]
+    fn unreadable_region_is_quarantined() {
+        let directory = test_directory("garbage");
+        let path = directory.join("r.0.0.0.vxr");
+        fs::write(&path, b"garbage").unwrap();
+
+        upgrade_region_file(&path).unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(quarantined(&directory).len(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    io,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use bevy::{
+    app::AppExit,
+    tasks::{IoTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use futures_lite::future::{block_on, poll_once};
+
+use crate::{player::PlayerCamera, prelude::*, world::cache::DroppedChunkCache};
+
+use super::ChunkStorage;
+
+pub struct SaveChunkPlugin;
+
+impl Plugin for SaveChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveSettings>()
+            .init_resource::<SaveChunkQueue>()
+            .init_resource::<UnloadedChunks>()
+            .init_resource::<SaveTask>()
+            .init_resource::<AutosaveTimer>()
+            .add_systems(
+                Update,
+                (autosave, handle_save_queue, handle_save_task, update_center),
+            )
+            .add_systems(Last, save_on_exit);
+    }
+}
+
+#[derive(Resource)]
+pub struct SaveSettings {
+    /// How often modified chunks that are still loaded are saved.
+    pub autosave_interval: Duration,
+    /// The most chunks written by a single save task.
+    pub max_batch_size: usize,
+}
+
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            autosave_interval: Duration::from_secs(30),
+            max_batch_size: 64,
+        }
+    }
+}
+
+pub struct SaveChunk;
+
+/// Chunks waiting to be written to disk.
+pub type SaveChunkQueue = DistanceOrderedQueue<ChunkPos, SaveChunk>;
+
+/// Chunks removed from the [`VoxelWorld`] before their changes were saved.
+///
+/// A chunk stays here until a save task has written it, so that loading it again in the meantime
+/// restores it from memory rather than from its stale copy on disk.
+#[derive(Default, Resource)]
+pub struct UnloadedChunks {
+    chunks: HashMap<ChunkPos, UnloadedChunk>,
+}
+
+struct UnloadedChunk {
+    chunk: Arc<RwLock<VoxelChunk>>,
+    /// Whether the chunk has changes that no save task has taken a snapshot of.
+    unsaved: bool,
+}
+
+impl UnloadedChunks {
+    /// Keeps a chunk until it is saved. A chunk without unsaved changes is waiting for the save
+    /// task in flight, and is released once it finishes.
+    pub fn insert(&mut self, position: ChunkPos, chunk: Arc<RwLock<VoxelChunk>>, unsaved: bool) {
+        self.chunks
+            .insert(position, UnloadedChunk { chunk, unsaved });
+    }
+
+    /// Removes an unsaved chunk so that it can be loaded again.
+    pub fn take(&mut self, position: &ChunkPos) -> Option<VoxelChunk> {
+        let chunk = self.chunks.remove(position)?.chunk;
+
+        // A save task may still hold the chunk.
+        Some(match Arc::try_unwrap(chunk) {
+            Ok(chunk) => chunk.into_inner().unwrap(),
+            Err(chunk) => chunk.read().unwrap().clone(),
+        })
+    }
+
+    /// Marks a chunk as saved, returning it if it had unsaved changes.
+    fn mark_saved(&mut self, position: &ChunkPos) -> Option<Arc<RwLock<VoxelChunk>>> {
+        let unloaded = self.chunks.get_mut(position)?;
+
+        std::mem::replace(&mut unloaded.unsaved, false).then(|| unloaded.chunk.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+/// The save task in flight. Only one runs at a time so that batches are written in order.
+#[derive(Default, Resource)]
+pub struct SaveTask {
+    task: Option<Task<(Vec<ChunkPos>, io::Result<()>)>>,
+    positions: HashSet<ChunkPos>,
+}
+
+impl SaveTask {
+    /// Returns whether a chunk is being written by the task in flight.
+    pub fn contains(&self, position: &ChunkPos) -> bool {
+        self.positions.contains(position)
+    }
+}
+
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
+impl FromWorld for AutosaveTimer {
+    fn from_world(world: &mut World) -> Self {
+        let settings = world.resource::<SaveSettings>();
+
+        Self(Timer::new(settings.autosave_interval, TimerMode::Repeating))
+    }
+}
+
+fn update_center(
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+    mut queue: ResMut<SaveChunkQueue>,
+) {
+    let camera = camera.single();
+
+    let center = ChunkPos::from_translation(camera.translation());
+
+    queue.update_center(center)
+}
+
+fn autosave(
+    time: Res<Time>,
+    settings: Res<SaveSettings>,
+    mut timer: ResMut<AutosaveTimer>,
+    world: Res<VoxelWorld>,
+    unloaded: Res<UnloadedChunks>,
+    mut queue: ResMut<SaveChunkQueue>,
+) {
+    if settings.is_changed() {
+        timer.0.set_duration(settings.autosave_interval);
+    }
+
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    for position in world.unsaved() {
+        queue.push(*position);
+    }
+
+    // Retry unloaded chunks whose save failed.
+    for position in unloaded.chunks.keys() {
+        queue.push(*position);
+    }
+}
+
+fn handle_save_queue(
+    mut world: ResMut<VoxelWorld>,
+    mut unloaded: ResMut<UnloadedChunks>,
+    storage: Res<ChunkStorage>,
+    settings: Res<SaveSettings>,
+    mut queue: ResMut<SaveChunkQueue>,
+    mut save_task: ResMut<SaveTask>,
+) {
+    if save_task.task.is_some() || queue.is_empty() {
+        return;
+    }
+
+    let mut chunks = Vec::new();
+
+    while let Some(position) = queue.pop() {
+        let chunk = match world.get(&position) {
+            Some(chunk) => world.mark_saved(&position).then_some(chunk),
+            None => unloaded.mark_saved(&position),
+        };
+
+        // Save a snapshot so that the task never holds a lock that an edit would wait on. Later
+        // edits mark the chunk as unsaved again.
+        if let Some(chunk) = chunk {
+            let snapshot = chunk.read().unwrap().clone();
+            chunks.push((position, snapshot));
+        }
+
+        if chunks.len() >= settings.max_batch_size {
+            break;
+        }
+    }
+
+    if chunks.is_empty() {
+        return;
+    }
+
+    save_task.positions = chunks.iter().map(|(position, _)| *position).collect();
+
+    let storage = storage.get();
+    let task = IoTaskPool::get().spawn(async move {
+        let result = storage.save_chunks(chunks.iter().map(|(position, chunk)| (*position, chunk)));
+
+        (
+            chunks.into_iter().map(|(position, _)| position).collect(),
+            result,
+        )
+    });
+
+    save_task.task = Some(task);
+}
+
+fn handle_save_task(
+    mut world: ResMut<VoxelWorld>,
+    mut unloaded: ResMut<UnloadedChunks>,
+    mut cache: ResMut<DroppedChunkCache>,
+    mut save_task: ResMut<SaveTask>,
+) {
+    let Some(task) = &mut save_task.task else {
+        return;
+    };
+
+    if let Some((positions, result)) = block_on(poll_once(task)) {
+        save_task.task = None;
+        save_task.positions.clear();
+        finish_save(&mut world, &mut unloaded, &mut cache, positions, result);
+    }
+}
+
+/// Moves the unloaded chunks a save task wrote to the [`DroppedChunkCache`], or marks its chunks
+/// for another attempt if it failed.
+fn finish_save(
+    world: &mut VoxelWorld,
+    unloaded: &mut UnloadedChunks,
+    cache: &mut DroppedChunkCache,
+    positions: Vec<ChunkPos>,
+    result: io::Result<()>,
+) {
+    match result {
+        Ok(()) => {
+            for position in positions {
+                // The chunk may have been changed since its snapshot was taken, in which case it
+                // is still waiting for another save.
+                if unloaded
+                    .chunks
+                    .get(&position)
+                    .is_some_and(|unloaded| !unloaded.unsaved)
+                {
+                    let chunk = unloaded.chunks.remove(&position).unwrap().chunk;
+                    cache.insert(position, chunk);
+                }
+            }
+        }
+        Err(err) => {
+            error!("failed to save {} chunks: {err}", positions.len());
+
+            for position in positions {
+                match unloaded.chunks.get_mut(&position) {
+                    Some(unloaded) => unloaded.unsaved = true,
+                    None => world.mark_unsaved(position),
+                }
+            }
+        }
+    }
+}
+
+/// Waits for the save in flight and writes every unsaved chunk before the app exits.
+fn save_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    mut world: ResMut<VoxelWorld>,
+    mut unloaded: ResMut<UnloadedChunks>,
+    mut cache: ResMut<DroppedChunkCache>,
+    storage: Res<ChunkStorage>,
+    mut save_task: ResMut<SaveTask>,
+) {
+    if exit_events.iter().next().is_none() {
+        return;
+    }
+
+    if let Some(task) = save_task.task.take() {
+        save_task.positions.clear();
+        let (positions, result) = block_on(task);
+        finish_save(&mut world, &mut unloaded, &mut cache, positions, result);
+    }
+
+    let chunks: Vec<_> = world
+        .unsaved()
+        .filter_map(|position| Some((*position, world.get(position)?)))
+        .chain(
+            unloaded
+                .chunks
+                .drain()
+                .map(|(position, unloaded)| (position, unloaded.chunk)),
+        )
+        .collect();
+
+    let guards: Vec<_> = chunks
+        .iter()
+        .map(|(position, chunk)| (*position, chunk.read().unwrap()))
+        .collect();
+
+    if let Err(err) = storage
+        .get()
+        .save_chunks(guards.iter().map(|(position, chunk)| (*position, &**chunk)))
+    {
+        error!("failed to save world: {err}");
+    }
+}
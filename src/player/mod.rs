@@ -1,14 +1,15 @@
 use crate::{
+    persistence::metadata::WorldMetadata,
     prelude::*,
     render::RenderSettings,
     world::{
         chunk::{ChunkEntityMap, DropChunkQueue, LoadChunkQueue},
-        heightmap::{DropHeightmapQueue, HeightmapEntityMap, LoadHeightmapQueue},
+        heightmap::{DropHeightmapQueue, HeightmapEntityMap},
     },
 };
 use bevy::{
     core_pipeline::experimental::taa::TemporalAntiAliasBundle, input::mouse::MouseMotion,
-    math::Vec3Swizzles, pbr::ScreenSpaceAmbientOcclusionBundle, window::PrimaryWindow,
+    pbr::ScreenSpaceAmbientOcclusionBundle, window::PrimaryWindow,
 };
 use bevy_atmosphere::prelude::{AtmosphereCamera, AtmospherePlugin};
 use bevy_dolly::prelude::*;
@@ -42,8 +43,14 @@ pub struct PlayerCameraBundle {
     atmosphere_camera: AtmosphereCamera,
 }
 
-fn setup(mut commands: Commands, render_settings: Res<RenderSettings>) {
-    let transform = Transform::from_xyz(2.0, 5.0, 2.0).looking_at(Vec3::ZERO, Vec3::Y);
+fn setup(
+    mut commands: Commands,
+    render_settings: Res<RenderSettings>,
+    metadata: Res<WorldMetadata>,
+) {
+    let spawn = metadata.spawn();
+    let transform =
+        Transform::from_translation(spawn).looking_at(spawn - Vec3::new(2.0, 5.0, 2.0), Vec3::Y);
 
     commands
         .spawn(PlayerCameraBundle {
@@ -63,7 +70,7 @@ fn setup(mut commands: Commands, render_settings: Res<RenderSettings>) {
                 }),
                 ..default()
             },
-            atmosphere_camera: AtmosphereCamera::default(),
+            atmosphere_camera: AtmosphereCamera,
         })
         .insert(ScreenSpaceAmbientOcclusionBundle::default())
         .insert(TemporalAntiAliasBundle::default());
@@ -134,10 +141,9 @@ fn load_chunks(
     render_settings: Res<RenderSettings>,
     player_transform: Query<&GlobalTransform, With<PlayerCamera>>,
     mut chunk_queue: ResMut<LoadChunkQueue>,
-    mut heightmap_queue: ResMut<LoadHeightmapQueue>,
 ) {
     let view_distance = render_settings.view_radius;
-    let center = player_transform.single().translation();
+    let center = ChunkPos::from_translation(player_transform.single().translation());
     for x in 0..=(view_distance.x * 2) as i32 {
         for y in 0..=(view_distance.y * 2) as i32 {
             for z in 0..=(view_distance.z * 2) as i32 {
@@ -146,23 +152,10 @@ fn load_chunks(
                     y - view_distance.y as i32,
                     z - view_distance.z as i32,
                 );
-                let position = center.as_ivec3() / CHUNK_SIZE as i32 + offset;
-                chunk_queue.push(position);
+                chunk_queue.push(center + offset);
             }
         }
     }
-
-    let far_view_distance = render_settings.far_view_radius;
-    for x in 0..=(far_view_distance.x * 2) as i32 {
-        for y in 0..=(far_view_distance.y * 2) as i32 {
-            let offset = IVec2::new(
-                x - far_view_distance.x as i32,
-                y - far_view_distance.y as i32,
-            );
-            let position = center.xz().as_ivec2() / CHUNK_SIZE as i32 + offset;
-            // heightmap_queue.push(position);
-        }
-    }
 }
 
 fn drop_chunks(
@@ -173,11 +166,11 @@ fn drop_chunks(
     mut drop_chunk_queue: ResMut<DropChunkQueue>,
     mut drop_heightmap_queue: ResMut<DropHeightmapQueue>,
 ) {
-    let center = player_transform.single().translation();
+    let center = ChunkPos::from_translation(player_transform.single().translation());
 
     let view_distance = render_settings.view_radius;
     for &offset in chunk_entity_map.keys() {
-        let distance = (center.as_ivec3() / CHUNK_SIZE as i32 - offset).abs();
+        let distance = (center - offset).abs();
         if distance
             .cmpgt(view_distance.as_ivec3() + IVec3::splat(render_settings.drop_padding as i32))
             .any()
@@ -188,7 +181,7 @@ fn drop_chunks(
 
     let far_view_distance = render_settings.far_view_radius;
     for &offset in heightmap_entity_map.keys() {
-        let distance = (center.xz().as_ivec2() / CHUNK_SIZE as i32 - offset).abs();
+        let distance = (center.column() - offset).abs();
         if distance
             .cmpgt(far_view_distance.as_ivec2() + IVec2::splat(render_settings.drop_padding as i32))
             .any()
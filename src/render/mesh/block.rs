@@ -0,0 +1,45 @@
+use block_mesh_pop::{MergeVoxel, MeshVoxel, VoxelVisibility};
+
+use crate::prelude::*;
+
+/// A voxel resolved against the [`BlockRegistry`] into the properties the mesher needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshBlock {
+    pub voxel: Voxel,
+    visibility: BlockVisibility,
+    merge_key: u16,
+}
+
+impl MeshBlock {
+    pub fn new(registry: &BlockRegistry, voxel: Voxel) -> Self {
+        Self {
+            voxel,
+            visibility: registry.get(voxel).visibility,
+            merge_key: registry.merge_key(voxel),
+        }
+    }
+}
+
+impl MeshVoxel for MeshBlock {
+    fn get_visibility(&self) -> VoxelVisibility {
+        match self.visibility {
+            BlockVisibility::Empty => VoxelVisibility::Empty,
+            BlockVisibility::Translucent => VoxelVisibility::Translucent,
+            BlockVisibility::Opaque => VoxelVisibility::Opaque,
+        }
+    }
+}
+
+/// Blocks only merge when both their merge key and their state match.
+impl MergeVoxel for MeshBlock {
+    type MergeValue = (u16, u16);
+    type MergeValueFacingNeighbour = (u16, u16);
+
+    fn merge_value(&self) -> Self::MergeValue {
+        (self.merge_key, self.voxel.state())
+    }
+
+    fn merge_value_facing_neighbour(&self) -> Self::MergeValueFacingNeighbour {
+        (self.merge_key, self.voxel.state())
+    }
+}
@@ -9,10 +9,10 @@ use bevy::{
     tasks::{AsyncComputeTaskPool, Task},
 };
 use block_mesh_pop::{
-    greedy_quads, visible_faces_quads, LodMaterial, PopBuffer, QuadBuffer, VisitedBuffer,
+    visible_faces_quads, LodEasing, LodMaterial, PopBuffer, VisitedBuffer, WrappedMaterial,
 };
 use futures_lite::future::{block_on, poll_once};
-use ndshape::ConstShape;
+use ndshape::{AbstractShape, ConstShape};
 use once_cell::sync::Lazy;
 use thread_local::ThreadLocal;
 
@@ -22,22 +22,37 @@ use crate::{
     world::chunk::{Chunk, ChunkEntityMap},
 };
 
+use super::block::MeshBlock;
+
 use crate::render::RenderSettings;
 
 pub struct MeshChunkPlugin;
 
 impl Plugin for MeshChunkPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<MeshChunkQueue>().add_systems(
-            Update,
-            (handle_mesh_queue, handle_mesh_tasks, update_center),
-        );
+        app.init_resource::<MeshChunkQueue>()
+            .add_event::<ChunkMeshed>()
+            .add_systems(
+                Update,
+                (
+                    queue_modified_chunks,
+                    handle_mesh_queue,
+                    handle_mesh_tasks,
+                    update_center,
+                ),
+            );
     }
 }
 
 pub struct MeshChunk;
 
-pub type MeshChunkQueue = DistanceOrderedQueue<IVec3, MeshChunk>;
+pub type MeshChunkQueue = DistanceOrderedQueue<ChunkPos, MeshChunk>;
+
+/// Sent when a chunk's mesh has been rebuilt.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct ChunkMeshed {
+    pub position: ChunkPos,
+}
 
 #[derive(Component)]
 pub struct MeshChunkTask {
@@ -47,13 +62,26 @@ pub struct MeshChunkTask {
 static SHARED_GREEDY_BUFFER: Lazy<ThreadLocal<RefCell<VisitedBuffer>>> =
     Lazy::new(ThreadLocal::default);
 
+static SHARED_BLOCK_BUFFER: Lazy<ThreadLocal<RefCell<Box<[MeshBlock]>>>> =
+    Lazy::new(ThreadLocal::default);
+
+fn queue_modified_chunks(mut world: ResMut<VoxelWorld>, mut queue: ResMut<MeshChunkQueue>) {
+    for position in world.drain_modified() {
+        queue.push(position);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_mesh_queue(
     mut commands: Commands,
     mut queue: ResMut<MeshChunkQueue>,
     entity_map: Res<ChunkEntityMap>,
     world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
     tasks: Query<Entity, With<MeshChunkTask>>,
+    mut chunks: Query<(&mut Chunk, &mut Handle<Mesh>)>,
     settings: Res<RenderSettings>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
 ) {
     let thread_pool = AsyncComputeTaskPool::get();
 
@@ -66,7 +94,19 @@ fn handle_mesh_queue(
     while let Some(position) = queue.pop() {
         if let Some(entity) = entity_map.get(&position) {
             if let Some(chunk) = world.get(&position) {
-                let task = thread_pool.spawn(generate_chunk_mesh_impl(chunk));
+                // Uniform chunks have no visible faces, so they are marked meshed without a task
+                // and do not count towards the limit.
+                if chunk.read().unwrap().as_uniform().is_some() {
+                    commands.entity(entity).remove::<MeshChunkTask>();
+                    if let Ok((mut component, mut handle)) = chunks.get_mut(entity) {
+                        *handle = Handle::default();
+                        component.is_loaded = true;
+                    }
+                    meshed_events.send(ChunkMeshed { position });
+                    continue;
+                }
+
+                let task = thread_pool.spawn(generate_chunk_mesh_impl(chunk, registry.clone()));
 
                 commands.entity(entity).insert(MeshChunkTask { task });
             }
@@ -79,27 +119,49 @@ fn handle_mesh_queue(
     }
 }
 
+type MeshTaskQuery<'a> = (
+    Entity,
+    &'a mut Chunk,
+    &'a mut Handle<Mesh>,
+    Option<&'a Handle<LodMaterial<6>>>,
+    &'a mut MeshChunkTask,
+);
+
 fn handle_mesh_tasks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut standard_materials: ResMut<Assets<StandardMaterial>>,
     mut materials: ResMut<Assets<LodMaterial<6>>>,
-    mut tasks: Query<
-        (
-            Entity,
-            &mut Chunk,
-            &mut Handle<Mesh>,
-            &mut Handle<LodMaterial<6>>,
-            &mut MeshChunkTask,
-        ),
-        With<Chunk>,
-    >,
+    mut tasks: Query<MeshTaskQuery, With<Chunk>>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
 ) {
     for (entity, mut chunk, mut handle, material, mut task) in &mut tasks {
         if let Some((buckets, mesh)) = block_on(poll_once(&mut task.task)) {
             commands.entity(entity).remove::<MeshChunkTask>();
 
-            if let Some(material_handle) = materials.get_mut(&material) {
-                material_handle.buckets = unsafe { std::mem::transmute(buckets) };
+            let buckets: [UVec4; 2] = unsafe { std::mem::transmute(buckets) };
+            match material {
+                Some(material) => {
+                    if let Some(material_handle) = materials.get_mut(material) {
+                        material_handle.buckets = buckets;
+                    }
+                }
+                None if mesh.indices().is_some_and(|indices| !indices.is_empty()) => {
+                    let standard_material =
+                        standard_materials.add(StandardMaterial::from(Color::rgb(1.0, 1.0, 1.0)));
+                    let lod_material = materials.add(LodMaterial {
+                        size: UVec3::splat(64),
+                        max_lod: 6,
+                        period: 1024,
+                        easing: LodEasing::Sine,
+                        buckets,
+                    });
+                    commands.entity(entity).insert((
+                        WrappedMaterial::<StandardMaterial>::from(standard_material),
+                        lod_material,
+                    ));
+                }
+                None => {}
             }
             if let Some(mesh_handle) = meshes.get_mut(&handle) {
                 *mesh_handle = mesh;
@@ -107,6 +169,9 @@ fn handle_mesh_tasks(
                 *handle = meshes.add(mesh);
             }
             chunk.is_loaded = true;
+            meshed_events.send(ChunkMeshed {
+                position: chunk.position,
+            });
         }
     }
 }
@@ -117,23 +182,62 @@ fn update_center(
 ) {
     let camera = camera.single();
 
-    let center = camera.translation().as_ivec3() / CHUNK_SIZE as i32;
+    let center = ChunkPos::from_translation(camera.translation());
 
     queue.update_center(center)
 }
 
-async fn generate_chunk_mesh_impl(chunk: Arc<RwLock<VoxelChunk>>) -> ([u32; 8], Mesh) {
+async fn generate_chunk_mesh_impl(
+    chunk: Arc<RwLock<VoxelChunk>>,
+    registry: BlockRegistry,
+) -> ([u32; 8], Mesh) {
+    let data = mesh_chunk(&chunk.read().unwrap(), &registry);
+    let num_vertices = data.positions.len();
+
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, data.positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, data.colors);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, data.normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0; 2]; num_vertices]);
+    mesh.set_indices(Some(Indices::U32(data.indices)));
+
+    (data.buckets, mesh)
+}
+
+/// The geometry of a meshed chunk, with positions relative to the chunk's padded origin.
+pub struct ChunkMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Vertex colors in sRGB space.
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+    /// The number of quads in each level of detail bucket.
+    pub buckets: [u32; 8],
+}
+
+/// Meshes the visible faces of a chunk. This is the code path used for rendering, and is shared
+/// with the exporters so that exported geometry matches what is on screen.
+pub fn mesh_chunk(chunk: &VoxelChunk, registry: &BlockRegistry) -> ChunkMeshData {
     let mut visited_buffer = SHARED_GREEDY_BUFFER
         .get_or(|| RefCell::new(VisitedBuffer::new(ChunkShape::USIZE)))
         .borrow_mut();
 
-    let voxels = &chunk.read().unwrap().voxels;
+    let mut blocks = SHARED_BLOCK_BUFFER
+        .get_or(|| {
+            RefCell::new(
+                vec![MeshBlock::new(registry, Voxel::EMPTY); ChunkShape::USIZE].into_boxed_slice(),
+            )
+        })
+        .borrow_mut();
+
+    chunk
+        .voxels
+        .copy_mapped_to(&mut blocks[..], |voxel| MeshBlock::new(registry, voxel));
 
     let mut buffer = PopBuffer::<6, _>::new();
 
-    visible_faces_quads::<66, 66, 66, 6, _>(voxels.read_data(), &mut visited_buffer, &mut buffer);
-    // greedy_quads::<66, 66, 66, 6, _>(voxels.read_data(), &mut visited_buffer, &mut buffer);
+    visible_faces_quads::<66, 66, 66, 6, _>(&blocks[..], &mut visited_buffer, &mut buffer);
+    // greedy_quads::<66, 66, 66, 6, _>(&blocks[..], &mut visited_buffer, &mut buffer);
 
     let buckets = buffer.get_buckets();
 
@@ -150,14 +254,15 @@ async fn generate_chunk_mesh_impl(chunk: Arc<RwLock<VoxelChunk>>) -> ([u32; 8],
         indices.extend_from_slice(&face.quad_mesh_indices(positions.len() as u32));
         positions.extend_from_slice(&face.quad_mesh_positions(quad, 0, 1.0));
         normals.extend_from_slice(&face.quad_mesh_normals());
-        colors.extend_from_slice(&[voxels.voxel_at(quad.minimum).get_color().as_rgba_f32(); 4]);
+        let block = blocks[CHUNK_SHAPE.linearize(quad.minimum.to_array()) as usize];
+        colors.extend_from_slice(&[registry.get(block.voxel).color.as_rgba_f32(); 4]);
     }
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0; 2]; num_vertices]);
-    mesh.set_indices(Some(Indices::U32(indices.clone())));
-
-    (buckets, mesh)
+    ChunkMeshData {
+        positions,
+        normals,
+        colors,
+        indices,
+        buckets,
+    }
 }
@@ -1,7 +1,6 @@
 use std::{cmp, sync::Arc};
 
 use bevy::{
-    math::Vec3Swizzles,
     render::{mesh::Indices, render_resource::PrimitiveTopology},
     tasks::{AsyncComputeTaskPool, Task},
 };
@@ -10,11 +9,8 @@ use futures_lite::future::{block_on, poll_once};
 use crate::{
     generation::{chunk::ChunkGenerator, world::VoxelWorldGenerator},
     prelude::*,
-    render::RenderSettings,
-    world::{
-        chunk::Chunk,
-        heightmap::{HeightmapEntityMap, HeightmapMarker},
-    },
+    render::{mesh::chunk::ChunkMeshed, RenderSettings},
+    world::heightmap::{HeightmapEntityMap, HeightmapMarker},
 };
 
 pub struct MeshHeightmapPlugin;
@@ -23,14 +19,14 @@ impl Plugin for MeshHeightmapPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MeshHeightmapQueue>().add_systems(
             Update,
-            (handle_mesh_queue, handle_mesh_tasks, handle_chunk_load),
+            (handle_mesh_queue, handle_mesh_tasks, handle_chunk_meshed),
         );
     }
 }
 
 pub struct MeshHeightmap;
 
-pub type MeshHeightmapQueue = DistanceOrderedQueue<IVec2, MeshHeightmap>;
+pub type MeshHeightmapQueue = DistanceOrderedQueue<ColumnPos, MeshHeightmap>;
 
 #[derive(Component)]
 pub struct MeshHeightmapTask {
@@ -41,6 +37,7 @@ fn handle_mesh_queue(
     mut commands: Commands,
     mut queue: ResMut<MeshHeightmapQueue>,
     world_generator: Res<VoxelWorldGenerator>,
+    registry: Res<BlockRegistry>,
     entity_map: Res<HeightmapEntityMap>,
     tasks: Query<Entity, With<MeshHeightmapTask>>,
     settings: Res<RenderSettings>,
@@ -53,11 +50,14 @@ fn handle_mesh_queue(
         queue.len(),
     );
 
+    let chunk_generator = world_generator.get();
+
     while let Some(position) = queue.pop() {
         if let Some(entity) = entity_map.get(&position) {
             let task = thread_pool.spawn(generate_heightmap_mesh_impl(
-                world_generator.get(),
+                chunk_generator.clone(),
                 position,
+                registry.clone(),
             ));
 
             commands.entity(entity).insert(MeshHeightmapTask { task });
@@ -94,17 +94,15 @@ fn handle_mesh_tasks(
     }
 }
 
-fn handle_chunk_load(
-    chunks: Query<&Chunk, Changed<Chunk>>,
+fn handle_chunk_meshed(
+    mut meshed_events: EventReader<ChunkMeshed>,
     mut heightmaps: Query<&mut HeightmapMarker>,
     heightmap_entity_map: Res<HeightmapEntityMap>,
 ) {
-    for chunk in &chunks {
-        if chunk.is_loaded {
-            if let Some(entity) = heightmap_entity_map.get(&chunk.position.xz()) {
-                if let Ok(mut heightmap) = heightmaps.get_mut(entity) {
-                    heightmap.blocking.insert(chunk.position);
-                }
+    for event in meshed_events.iter() {
+        if let Some(entity) = heightmap_entity_map.get(&event.position.column()) {
+            if let Ok(mut heightmap) = heightmaps.get_mut(entity) {
+                heightmap.blocking.insert(event.position);
             }
         }
     }
@@ -118,14 +116,18 @@ struct GenerateHeightmapMeshResult {
 
 async fn generate_heightmap_mesh_impl(
     chunk_generator: Arc<ChunkGenerator>,
-    position: IVec2,
+    position: ColumnPos,
+    registry: BlockRegistry,
 ) -> GenerateHeightmapMeshResult {
     let heightmap = chunk_generator.generate_heightmap(position).await;
 
-    generate_heightmap_mesh(&heightmap).await
+    generate_heightmap_mesh(&heightmap, &registry).await
 }
 
-async fn generate_heightmap_mesh(heightmap: &Heightmap) -> GenerateHeightmapMeshResult {
+async fn generate_heightmap_mesh(
+    heightmap: &Heightmap,
+    registry: &BlockRegistry,
+) -> GenerateHeightmapMeshResult {
     let subdivisions = 16;
     let size = CHUNK_SIZE as f32;
     let z_vertex_count = subdivisions + 2;
@@ -150,6 +152,9 @@ async fn generate_heightmap_mesh(heightmap: &Heightmap) -> GenerateHeightmapMesh
             let nearest_z = z / 4 * 4 * 4;
             let nearest_x = x / 4 * 4 * 4;
             let height = heightmap.get(UVec2::new(nearest_x, nearest_z));
+            let color = registry
+                .get(heightmap.surface(UVec2::new(nearest_x, nearest_z)))
+                .color;
 
             if minimum > height {
                 minimum = height;
@@ -159,7 +164,7 @@ async fn generate_heightmap_mesh(heightmap: &Heightmap) -> GenerateHeightmapMesh
             }
 
             positions.push([tx * size, height as f32, tz * size]);
-            colors.push(Voxel(3).get_color().as_rgba_f32());
+            colors.push(color.as_rgba_f32());
             normals.push(up);
             uvs.push([tx, tz]);
         }
@@ -1,2 +1,3 @@
+pub mod block;
 pub mod chunk;
 pub mod heightmap;
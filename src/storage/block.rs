@@ -0,0 +1,299 @@
+use std::{fs, io, path::Path, sync::Arc};
+
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+/// The asset file block definitions are loaded from.
+pub const BLOCKS_PATH: &str = "assets/blocks.ron";
+
+/// How a block is treated by the mesher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum BlockVisibility {
+    Empty,
+    Translucent,
+    #[default]
+    Opaque,
+}
+
+/// Which faces the greedy mesher may merge a block with.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum MergeBehaviour {
+    /// Merge only with blocks of the same type.
+    #[default]
+    Block,
+    /// Merge with any block in the same named group.
+    Group(String),
+}
+
+/// A property of a block's state, such as its orientation or fluid level.
+///
+/// Each property takes the fewest state bits able to index its values, in declaration order.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StateProperty {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl StateProperty {
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        usize::BITS - self.values.len().saturating_sub(1).leading_zeros()
+    }
+}
+
+/// A block type as declared in the block asset file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockDefinition {
+    pub name: String,
+    #[serde(default = "default_color")]
+    pub color: Color,
+    #[serde(default)]
+    pub visibility: BlockVisibility,
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    #[serde(default)]
+    pub merge: MergeBehaviour,
+    #[serde(default)]
+    pub states: Vec<StateProperty>,
+}
+
+fn default_color() -> Color {
+    Color::WHITE
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+impl BlockDefinition {
+    fn air() -> Self {
+        Self {
+            name: "air".into(),
+            color: Color::NONE,
+            visibility: BlockVisibility::Empty,
+            solid: false,
+            merge: MergeBehaviour::Block,
+            states: Vec::new(),
+        }
+    }
+
+    /// Returns the number of state bits used by the block's properties.
+    pub fn state_bits(&self) -> u32 {
+        self.states.iter().map(StateProperty::bits).sum()
+    }
+
+    /// Returns the index of a state property's value in a voxel of this block.
+    pub fn state_index(&self, voxel: Voxel, property: &str) -> Option<u16> {
+        let (shift, bits, _) = self.property(property)?;
+
+        Some((voxel.state() >> shift) & ((1 << bits) - 1))
+    }
+
+    /// Returns the value of a state property in a voxel of this block.
+    pub fn state_value(&self, voxel: Voxel, property: &str) -> Option<&str> {
+        let (_, _, state_property) = self.property(property)?;
+        let index = self.state_index(voxel, property)?;
+
+        state_property
+            .values
+            .get(index as usize)
+            .map(String::as_str)
+    }
+
+    /// Returns the voxel with a state property set to the value at `index`, or `None` if the
+    /// property or value does not exist.
+    pub fn with_state_index(&self, voxel: Voxel, property: &str, index: u16) -> Option<Voxel> {
+        let (shift, bits, state_property) = self.property(property)?;
+
+        if index as usize >= state_property.values.len() {
+            return None;
+        }
+
+        let mask = ((1 << bits) - 1) << shift;
+
+        Some(voxel.with_state((voxel.state() & !mask) | (index << shift)))
+    }
+
+    /// Returns the voxel with a state property set to `value`, or `None` if the property or
+    /// value does not exist.
+    pub fn with_state_value(&self, voxel: Voxel, property: &str, value: &str) -> Option<Voxel> {
+        let (_, _, state_property) = self.property(property)?;
+        let index = state_property.values.iter().position(|v| v == value)?;
+
+        self.with_state_index(voxel, property, index as u16)
+    }
+
+    /// Returns the shift and width of a state property within the state bits.
+    fn property(&self, name: &str) -> Option<(u32, u32, &StateProperty)> {
+        let mut shift = 0;
+
+        for property in &self.states {
+            if property.name == name {
+                return Some((shift, property.bits(), property));
+            }
+            shift += property.bits();
+        }
+
+        None
+    }
+}
+
+/// The set of block types known to the world.
+///
+/// Block ids are assigned in the order blocks are declared, starting at 1; id 0 is always `air`.
+/// Chunks are stored by id, so new blocks should be appended to the asset file to keep saved
+/// worlds valid. Lookups by voxel ignore its state bits.
+#[derive(Clone, Resource)]
+pub struct BlockRegistry {
+    inner: Arc<BlockRegistryInner>,
+}
+
+struct BlockRegistryInner {
+    blocks: Vec<BlockDefinition>,
+    ids: HashMap<String, Voxel>,
+    merge_keys: Vec<u16>,
+}
+
+impl BlockRegistry {
+    pub fn new(definitions: impl IntoIterator<Item = BlockDefinition>) -> io::Result<Self> {
+        let mut blocks = vec![BlockDefinition::air()];
+        let mut ids = HashMap::new();
+        ids.insert(blocks[0].name.clone(), Voxel::EMPTY);
+
+        for definition in definitions {
+            if ids.contains_key(&definition.name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("block {:?} is declared more than once", definition.name),
+                ));
+            }
+            if blocks.len() > Voxel::MAX_ID as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many block types",
+                ));
+            }
+            if let Some(property) = definition
+                .states
+                .iter()
+                .find(|property| property.values.is_empty())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "state {:?} of block {:?} has no values",
+                        property.name, definition.name
+                    ),
+                ));
+            }
+            if definition.state_bits() > Voxel::STATE_BITS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "states of block {:?} need {} bits, but only {} are available",
+                        definition.name,
+                        definition.state_bits(),
+                        Voxel::STATE_BITS
+                    ),
+                ));
+            }
+
+            ids.insert(definition.name.clone(), Voxel::new(blocks.len() as u16, 0));
+            blocks.push(definition);
+        }
+
+        // Blocks merge with each other when they share a key. Groups take the key of the first
+        // block declared in them.
+        let mut groups = HashMap::new();
+        let merge_keys = blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| match &block.merge {
+                MergeBehaviour::Block => id as u16,
+                MergeBehaviour::Group(group) => *groups.entry(group.clone()).or_insert(id as u16),
+            })
+            .collect();
+
+        Ok(Self {
+            inner: Arc::new(BlockRegistryInner {
+                blocks,
+                ids,
+                merge_keys,
+            }),
+        })
+    }
+
+    /// Loads the block definitions from a RON file containing a list of blocks.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let definitions: Vec<BlockDefinition> = ron::from_str(&source)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Self::new(definitions)
+    }
+
+    /// Returns the definition of a block, falling back to `air` for unknown ids.
+    #[inline]
+    pub fn get(&self, voxel: Voxel) -> &BlockDefinition {
+        self.inner
+            .blocks
+            .get(voxel.id() as usize)
+            .unwrap_or(&self.inner.blocks[0])
+    }
+
+    /// Returns the voxel for a block name, with every state property at its first value.
+    #[inline]
+    pub fn id(&self, name: &str) -> Option<Voxel> {
+        self.inner.ids.get(name).copied()
+    }
+
+    /// Returns the key the mesher compares to decide whether two blocks may be merged.
+    #[inline]
+    pub fn merge_key(&self, voxel: Voxel) -> u16 {
+        self.inner
+            .merge_keys
+            .get(voxel.id() as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the visible block whose color is closest to `color`, or `air` if there is none.
+    pub fn nearest_color(&self, color: Color) -> Voxel {
+        let target = Vec3::from_slice(&color.as_rgba_f32()[..3]);
+
+        self.iter()
+            .filter(|(_, block)| block.visibility != BlockVisibility::Empty)
+            .min_by(|(_, a), (_, b)| {
+                let a = Vec3::from_slice(&a.color.as_rgba_f32()[..3]).distance_squared(target);
+                let b = Vec3::from_slice(&b.color.as_rgba_f32()[..3]).distance_squared(target);
+                a.total_cmp(&b)
+            })
+            .map(|(voxel, _)| voxel)
+            .unwrap_or(Voxel::EMPTY)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.blocks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Voxel, &BlockDefinition)> + '_ {
+        self.inner
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| (Voxel::new(id as u16, 0), block))
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::load(BLOCKS_PATH)
+            .unwrap_or_else(|err| panic!("failed to load blocks from {BLOCKS_PATH}: {err}"))
+    }
+}
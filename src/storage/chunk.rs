@@ -13,7 +13,24 @@ pub type FlatChunkShape = ConstShape2u32<PADDED_CHUNK_SIZE, PADDED_CHUNK_SIZE>;
 pub const CHUNK_SHAPE: ChunkShape = ChunkShape {};
 pub const FLAT_CHUNK_SHAPE: FlatChunkShape = FlatChunkShape {};
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct VoxelChunk {
-    pub voxels: VoxelBuffer,
+    pub voxels: PaletteBuffer,
+}
+
+impl VoxelChunk {
+    /// Creates a chunk filled with a single voxel, stored without per-voxel data.
+    #[inline]
+    pub fn uniform(voxel: Voxel) -> Self {
+        Self {
+            voxels: PaletteBuffer::filled(voxel),
+        }
+    }
+
+    /// Returns the voxel filling the whole chunk, padding included, if the chunk is uniform.
+    /// Uniform chunks have no visible faces and are not meshed.
+    #[inline]
+    pub fn as_uniform(&self) -> Option<Voxel> {
+        self.voxels.uniform()
+    }
 }
@@ -6,6 +6,8 @@ use crate::prelude::*;
 #[derive(Clone)]
 pub struct Heightmap {
     data: [i32; PADDED_CHUNK_SIZE.pow(2) as usize],
+    /// The block covering each column.
+    surface: [Voxel; PADDED_CHUNK_SIZE.pow(2) as usize],
 }
 
 impl Default for Heightmap {
@@ -18,6 +20,7 @@ impl Heightmap {
     pub fn new() -> Self {
         Self {
             data: [0; PADDED_CHUNK_SIZE.pow(2) as usize],
+            surface: [Voxel::EMPTY; PADDED_CHUNK_SIZE.pow(2) as usize],
         }
     }
 
@@ -31,6 +34,20 @@ impl Heightmap {
         &mut self.data[FLAT_CHUNK_SHAPE.linearize(position.to_array()) as usize]
     }
 
+    #[inline]
+    pub fn surface(&self, position: UVec2) -> Voxel {
+        self.surface[FLAT_CHUNK_SHAPE.linearize(position.to_array()) as usize]
+    }
+
+    /// Returns the lowest and highest heights, padding included.
+    pub fn bounds(&self) -> (i32, i32) {
+        self.data
+            .iter()
+            .fold((i32::MAX, i32::MIN), |(minimum, maximum), height| {
+                (minimum.min(*height), maximum.max(*height))
+            })
+    }
+
     #[inline]
     pub fn iter(&self) -> impl Iterator<Item = (UVec2, i32)> + '_ {
         self.data.iter().enumerate().map(|(i, height)| {
@@ -50,4 +67,11 @@ impl Heightmap {
             )
         })
     }
+
+    /// Iterates over the height and surface block of every column, in the order of
+    /// [`Self::iter`].
+    #[inline]
+    pub fn columns_mut(&mut self) -> impl Iterator<Item = (&mut i32, &mut Voxel)> + '_ {
+        self.data.iter_mut().zip(self.surface.iter_mut())
+    }
 }
@@ -1,12 +1,20 @@
 pub mod array_buffer;
+pub mod block;
 pub mod chunk;
 pub mod heightmap;
+pub mod palette_buffer;
+pub mod position;
+pub mod volume;
 pub mod voxel;
 pub mod voxel_world;
 
 pub use array_buffer::*;
+pub use block::*;
 pub use chunk::*;
 pub use heightmap::*;
+pub use palette_buffer::*;
+pub use position::*;
+pub use volume::*;
 pub use voxel::*;
 pub use voxel_world::*;
 
@@ -16,6 +24,13 @@ pub struct StoragePlugin;
 
 impl Plugin for StoragePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<VoxelWorld>();
+        app.init_resource::<BlockRegistry>()
+            .init_resource::<VoxelWorld>()
+            .add_event::<VoxelsChanged>()
+            .add_systems(PostUpdate, send_voxel_change_events);
     }
 }
+
+fn send_voxel_change_events(mut world: ResMut<VoxelWorld>, mut events: EventWriter<VoxelsChanged>) {
+    events.send_batch(world.drain_changes().map(|extent| VoxelsChanged { extent }));
+}
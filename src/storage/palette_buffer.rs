@@ -0,0 +1,374 @@
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+use ilattice::prelude::Extent;
+use ndshape::AbstractShape;
+
+use crate::prelude::*;
+
+const VOLUME: usize = PADDED_CHUNK_SIZE.pow(3) as usize;
+
+/// A chunk-sized voxel buffer that stores each voxel as an index into a palette of the distinct
+/// voxels it contains.
+///
+/// Indices are bit-packed into `u64` words using the smallest power-of-two width that can address
+/// the palette, so a buffer containing a single voxel type takes no index storage at all. The
+/// palette grows transparently as new voxel types are written.
+#[derive(Clone)]
+pub struct PaletteBuffer {
+    palette: Vec<Voxel>,
+    bits: u32,
+    words: Box<[u64]>,
+}
+
+impl PaletteBuffer {
+    #[inline]
+    pub fn new() -> Self {
+        Self::filled(Voxel::EMPTY)
+    }
+
+    #[inline]
+    pub fn filled(value: Voxel) -> Self {
+        Self {
+            palette: vec![value],
+            bits: 0,
+            words: Box::default(),
+        }
+    }
+
+    /// Builds a buffer from its serialized parts, returning `None` if they are inconsistent.
+    pub fn from_raw_parts(palette: Vec<Voxel>, bits: u32, words: Box<[u64]>) -> Option<Self> {
+        if palette.is_empty()
+            || bits != bits_for_len(palette.len())
+            || words.len() != words_for_bits(bits)
+        {
+            return None;
+        }
+
+        let buffer = Self {
+            palette,
+            bits,
+            words,
+        };
+
+        (0..VOLUME)
+            .all(|index| buffer.palette_index(index) < buffer.palette.len())
+            .then_some(buffer)
+    }
+
+    #[inline]
+    pub fn palette(&self) -> &[Voxel] {
+        &self.palette
+    }
+
+    #[inline]
+    pub fn bits_per_index(&self) -> u32 {
+        self.bits
+    }
+
+    #[inline]
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Returns the voxel filling the whole buffer, if it is stored as a single value. A buffer
+    /// that became uniform through writes is only detected after [`Self::compact`].
+    #[inline]
+    pub fn uniform(&self) -> Option<Voxel> {
+        (self.bits == 0).then(|| self.palette[0])
+    }
+
+    #[inline]
+    pub fn voxel_at_index(&self, index: usize) -> Voxel {
+        self.palette[self.palette_index(index)]
+    }
+
+    #[inline]
+    pub fn voxel_at_index_mut(&mut self, index: usize) -> VoxelMut<'_> {
+        let voxel = self.voxel_at_index(index);
+
+        VoxelMut {
+            buffer: self,
+            index,
+            original: voxel,
+            voxel,
+        }
+    }
+
+    #[inline]
+    pub fn set_voxel_at_index(&mut self, index: usize, value: Voxel) {
+        let palette_index = self.palette_index_of(value);
+        self.set_palette_index(index, palette_index);
+    }
+
+    #[inline]
+    pub fn voxel_at(&self, position: UVec3) -> Voxel {
+        self.voxel_at_index(CHUNK_SHAPE.linearize(position.to_array()) as usize)
+    }
+
+    #[inline]
+    pub fn voxel_at_mut(&mut self, position: UVec3) -> VoxelMut<'_> {
+        self.voxel_at_index_mut(CHUNK_SHAPE.linearize(position.to_array()) as usize)
+    }
+
+    #[inline]
+    pub fn set_voxel_at(&mut self, position: UVec3, value: Voxel) {
+        self.set_voxel_at_index(CHUNK_SHAPE.linearize(position.to_array()) as usize, value);
+    }
+
+    pub fn fill_extent(&mut self, extent: Extent<UVec3>, value: Voxel) {
+        if extent.minimum == UVec3::ZERO && extent.shape == UVec3::splat(PADDED_CHUNK_SIZE) {
+            *self = Self::filled(value);
+            return;
+        }
+
+        let palette_index = self.palette_index_of(value);
+        let maximum = extent.minimum + extent.shape;
+
+        for z in extent.minimum.z..maximum.z {
+            for y in extent.minimum.y..maximum.y {
+                for x in extent.minimum.x..maximum.x {
+                    let index = CHUNK_SHAPE.linearize([x, y, z]) as usize;
+                    self.set_palette_index(index, palette_index);
+                }
+            }
+        }
+    }
+
+    /// Decompresses every voxel into `out`, which must hold a full padded chunk.
+    pub fn copy_to(&self, out: &mut [Voxel]) {
+        self.copy_mapped_to(out, |voxel| voxel);
+    }
+
+    /// Decompresses every voxel into `out`, which must hold a full padded chunk, converting each
+    /// one with `f`. `f` is called once per palette entry rather than once per voxel.
+    pub fn copy_mapped_to<T: Copy>(&self, out: &mut [T], f: impl Fn(Voxel) -> T) {
+        if self.bits == 0 {
+            out[..VOLUME].fill(f(self.palette[0]));
+            return;
+        }
+
+        let palette: Vec<T> = self.palette.iter().map(|voxel| f(*voxel)).collect();
+
+        for (index, value) in out[..VOLUME].iter_mut().enumerate() {
+            *value = palette[self.palette_index(index)];
+        }
+    }
+
+    /// Removes palette entries that are no longer referenced, shrinking the index width if
+    /// possible.
+    pub fn compact(&mut self) {
+        if self.bits == 0 {
+            return;
+        }
+
+        let mut used = vec![false; self.palette.len()];
+        for index in 0..VOLUME {
+            used[self.palette_index(index)] = true;
+        }
+
+        if used.iter().all(|used| *used) {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (old, voxel) in self.palette.iter().enumerate() {
+            if used[old] {
+                remap[old] = palette.len();
+                palette.push(*voxel);
+            }
+        }
+
+        self.repack(palette, |old| remap[old]);
+    }
+
+    #[inline]
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1 << self.bits) - 1;
+
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    #[inline]
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits == 0 {
+            return;
+        }
+
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1 << self.bits) - 1) << shift;
+        let word = &mut self.words[index / per_word];
+
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+
+    /// Returns the palette index of `value`, adding it to the palette and widening the indices
+    /// if it is not present yet.
+    fn palette_index_of(&mut self, value: Voxel) -> usize {
+        if let Some(palette_index) = self.palette.iter().position(|voxel| *voxel == value) {
+            return palette_index;
+        }
+
+        self.palette.push(value);
+
+        if bits_for_len(self.palette.len()) != self.bits {
+            let palette = mem::take(&mut self.palette);
+            self.repack(palette, |old| old);
+        }
+
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, palette: Vec<Voxel>, remap: impl Fn(usize) -> usize) {
+        let mut repacked = Self {
+            bits: bits_for_len(palette.len()),
+            words: vec![0; words_for_bits(bits_for_len(palette.len()))].into_boxed_slice(),
+            palette,
+        };
+
+        if repacked.bits != 0 {
+            for index in 0..VOLUME {
+                repacked.set_palette_index(index, remap(self.palette_index(index)));
+            }
+        }
+
+        *self = repacked;
+    }
+}
+
+impl Default for PaletteBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the smallest power-of-two index width able to address a palette of `len` entries.
+fn bits_for_len(len: usize) -> u32 {
+    match len {
+        0..=1 => 0,
+        2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        17..=256 => 8,
+        _ => 16,
+    }
+}
+
+fn words_for_bits(bits: u32) -> usize {
+    if bits == 0 {
+        0
+    } else {
+        let per_word = 64 / bits as usize;
+        VOLUME.div_ceil(per_word)
+    }
+}
+
+/// A mutable reference to a voxel in a [`PaletteBuffer`] that is written back when dropped.
+pub struct VoxelMut<'a> {
+    buffer: &'a mut PaletteBuffer,
+    index: usize,
+    original: Voxel,
+    voxel: Voxel,
+}
+
+impl Deref for VoxelMut<'_> {
+    type Target = Voxel;
+
+    fn deref(&self) -> &Self::Target {
+        &self.voxel
+    }
+}
+
+impl DerefMut for VoxelMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.voxel
+    }
+}
+
+impl Drop for VoxelMut<'_> {
+    fn drop(&mut self) {
+        if self.voxel != self.original {
+            self.buffer.set_voxel_at_index(self.index, self.voxel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A voxel for every index, cycling through `types` distinct values.
+    fn pattern(index: usize, types: usize) -> Voxel {
+        Voxel((index * 7919 % types) as u16)
+    }
+
+    #[test]
+    fn palette_grows_through_every_width() {
+        for types in [1, 2, 3, 5, 17, 300] {
+            let mut buffer = PaletteBuffer::new();
+            for index in 0..VOLUME {
+                buffer.set_voxel_at_index(index, pattern(index, types));
+            }
+
+            assert_eq!(buffer.bits_per_index(), bits_for_len(types));
+            assert!((0..VOLUME).all(|index| buffer.voxel_at_index(index) == pattern(index, types)));
+        }
+    }
+
+    #[test]
+    fn compact_drops_unused_voxels() {
+        let mut buffer = PaletteBuffer::filled(Voxel(1));
+        buffer.set_voxel_at_index(10, Voxel(2));
+        buffer.set_voxel_at_index(20, Voxel(3));
+        buffer.set_voxel_at_index(10, Voxel(1));
+
+        buffer.compact();
+        assert_eq!(buffer.palette(), &[Voxel(1), Voxel(3)]);
+        assert_eq!(buffer.voxel_at_index(20), Voxel(3));
+
+        buffer.set_voxel_at_index(20, Voxel(1));
+        buffer.compact();
+        assert_eq!(buffer.uniform(), Some(Voxel(1)));
+    }
+
+    #[test]
+    fn raw_parts_round_trip() {
+        let mut buffer = PaletteBuffer::new();
+        for index in 0..VOLUME {
+            buffer.set_voxel_at_index(index, pattern(index, 5));
+        }
+
+        let copy = PaletteBuffer::from_raw_parts(
+            buffer.palette().to_vec(),
+            buffer.bits_per_index(),
+            buffer.words().into(),
+        )
+        .unwrap();
+        assert!((0..VOLUME).all(|index| copy.voxel_at_index(index) == pattern(index, 5)));
+    }
+
+    #[test]
+    fn inconsistent_raw_parts() {
+        let palette = vec![Voxel(0), Voxel(1), Voxel(2)];
+        let words = vec![0; words_for_bits(2)];
+
+        assert!(PaletteBuffer::from_raw_parts(Vec::new(), 0, Box::default()).is_none());
+        assert!(PaletteBuffer::from_raw_parts(palette.clone(), 4, words.clone().into()).is_none());
+        assert!(PaletteBuffer::from_raw_parts(palette.clone(), 2, words[1..].into()).is_none());
+
+        // The third palette entry is the last valid index.
+        let mut out_of_range = words;
+        out_of_range[0] = 3;
+        assert!(PaletteBuffer::from_raw_parts(palette, 2, out_of_range.into()).is_none());
+    }
+}
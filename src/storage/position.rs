@@ -0,0 +1,212 @@
+use std::{
+    fmt,
+    ops::{Add, Sub},
+};
+
+use bevy::math::Vec3Swizzles;
+
+use crate::prelude::*;
+
+/// The position of a chunk, in chunks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos(pub IVec3);
+
+/// The position of a voxel, in voxels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorldVoxelPos(pub IVec3);
+
+/// The position of a voxel within its chunk, excluding padding. Each component is in
+/// `0..CHUNK_SIZE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LocalVoxelPos(pub UVec3);
+
+/// The horizontal position of a column of chunks, in chunks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ColumnPos(pub IVec2);
+
+#[inline]
+fn floor_div(value: IVec3, divisor: i32) -> IVec3 {
+    IVec3::new(
+        value.x.div_euclid(divisor),
+        value.y.div_euclid(divisor),
+        value.z.div_euclid(divisor),
+    )
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    /// Returns the chunk containing a world-space translation.
+    pub fn from_translation(translation: Vec3) -> Self {
+        WorldVoxelPos::from_translation(translation).chunk()
+    }
+
+    /// Returns the world position of the voxel at the chunk's local origin.
+    pub fn origin(self) -> WorldVoxelPos {
+        WorldVoxelPos(self.0 * CHUNK_SIZE as i32)
+    }
+
+    /// Returns the world position of the first voxel of the chunk's padded buffer.
+    pub fn padded_origin(self) -> WorldVoxelPos {
+        WorldVoxelPos(self.origin().0 - IVec3::ONE)
+    }
+
+    pub fn column(self) -> ColumnPos {
+        ColumnPos(self.0.xz())
+    }
+}
+
+impl WorldVoxelPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    /// Returns the voxel containing a world-space translation.
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self(translation.floor().as_ivec3())
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos(floor_div(self.0, CHUNK_SIZE as i32))
+    }
+
+    pub fn local(self) -> LocalVoxelPos {
+        LocalVoxelPos((self.0 - self.chunk().origin().0).as_uvec3())
+    }
+
+    pub fn split(self) -> (ChunkPos, LocalVoxelPos) {
+        (self.chunk(), self.local())
+    }
+}
+
+impl LocalVoxelPos {
+    /// Returns the position of the voxel within its chunk's padded buffer.
+    pub fn padded(self) -> UVec3 {
+        self.0 + UVec3::ONE
+    }
+}
+
+impl ColumnPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self(IVec2::new(x, z))
+    }
+
+    /// Returns the column containing a world-space translation.
+    pub fn from_translation(translation: Vec3) -> Self {
+        ChunkPos::from_translation(translation).column()
+    }
+
+    /// Returns the horizontal world position of the voxel at the column's local origin.
+    pub fn origin(self) -> IVec2 {
+        self.0 * CHUNK_SIZE as i32
+    }
+
+    /// Returns the horizontal world position of the first voxel of the column's padded buffer.
+    pub fn padded_origin(self) -> IVec2 {
+        self.origin() - IVec2::ONE
+    }
+
+    pub fn with_y(self, y: i32) -> ChunkPos {
+        ChunkPos(self.0.extend_y(y))
+    }
+}
+
+impl Add<IVec3> for ChunkPos {
+    type Output = Self;
+
+    fn add(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub for ChunkPos {
+    type Output = IVec3;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+
+impl Add<IVec3> for WorldVoxelPos {
+    type Output = Self;
+
+    fn add(self, rhs: IVec3) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub for WorldVoxelPos {
+    type Output = IVec3;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+
+impl Add<IVec2> for ColumnPos {
+    type Output = Self;
+
+    fn add(self, rhs: IVec2) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl Sub for ColumnPos {
+    type Output = IVec2;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+
+impl LengthSquared for ChunkPos {
+    type Output = i32;
+
+    fn length_squared(self) -> Self::Output {
+        self.0.length_squared()
+    }
+}
+
+impl LengthSquared for ColumnPos {
+    type Output = i32;
+
+    fn length_squared(self) -> Self::Output {
+        self.0.length_squared()
+    }
+}
+
+impl DistanceOrd for ChunkPos {
+    type AsOrd = <IVec3 as DistanceOrd>::AsOrd;
+
+    fn distance_ord(self, center: Self) -> Self::AsOrd {
+        self.0.distance_ord(center.0)
+    }
+}
+
+impl DistanceOrd for ColumnPos {
+    type AsOrd = <IVec2 as DistanceOrd>::AsOrd;
+
+    fn distance_ord(self, center: Self) -> Self::AsOrd {
+        self.0.distance_ord(center.0)
+    }
+}
+
+impl fmt::Display for ChunkPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for WorldVoxelPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for ColumnPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::math::Vec3Swizzles;
+
+use crate::prelude::*;
+
+/// One of the three axes of a volume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Axis3 {
+    X,
+    Y,
+    Z,
+}
+
+/// The most voxels a volume read from a file may hold, so that corrupt or hostile dimensions are
+/// rejected instead of exhausting memory.
+pub const MAX_LOADED_VOLUME: u32 = 1 << 26;
+
+/// A dense box of voxels that is independent of the chunk grid.
+///
+/// Voxels are laid out with x varying fastest, then y, then z.
+#[derive(Clone)]
+pub struct VoxelVolume {
+    shape: UVec3,
+    data: Box<[Voxel]>,
+}
+
+impl VoxelVolume {
+    pub fn new(shape: UVec3) -> Self {
+        Self::filled(shape, Voxel::EMPTY)
+    }
+
+    pub fn filled(shape: UVec3, value: Voxel) -> Self {
+        Self {
+            shape,
+            data: vec![value; (shape.x * shape.y * shape.z) as usize].into_boxed_slice(),
+        }
+    }
+
+    /// Returns the number of voxels in a volume of the given shape, or `None` if it is more than
+    /// [`MAX_LOADED_VOLUME`].
+    pub fn checked_len(shape: UVec3) -> Option<u32> {
+        shape
+            .x
+            .checked_mul(shape.y)
+            .and_then(|area| area.checked_mul(shape.z))
+            .filter(|&len| len <= MAX_LOADED_VOLUME)
+    }
+
+    #[inline]
+    pub fn shape(&self) -> UVec3 {
+        self.shape
+    }
+
+    #[inline]
+    pub fn linearize(&self, position: UVec3) -> usize {
+        (position.x + self.shape.x * (position.y + self.shape.y * position.z)) as usize
+    }
+
+    #[inline]
+    pub fn delinearize(&self, index: usize) -> UVec3 {
+        let index = index as u32;
+
+        UVec3::new(
+            index % self.shape.x,
+            (index / self.shape.x) % self.shape.y,
+            index / (self.shape.x * self.shape.y),
+        )
+    }
+
+    #[inline]
+    pub fn voxel_at(&self, position: UVec3) -> Voxel {
+        self.data[self.linearize(position)]
+    }
+
+    #[inline]
+    pub fn voxel_at_mut(&mut self, position: UVec3) -> &mut Voxel {
+        &mut self.data[self.linearize(position)]
+    }
+
+    #[inline]
+    pub fn read_data(&self) -> &[Voxel] {
+        &self.data
+    }
+
+    /// Returns the volume rotated by a number of quarter turns around an axis. Positive turns
+    /// are counterclockwise when looking from the positive end of the axis towards the origin.
+    pub fn rotated(&self, axis: Axis3, quarter_turns: i32) -> Self {
+        let mut volume = self.clone();
+
+        for _ in 0..quarter_turns.rem_euclid(4) {
+            volume = volume.rotated_once(axis);
+        }
+
+        volume
+    }
+
+    /// Returns the volume mirrored along an axis.
+    pub fn mirrored(&self, axis: Axis3) -> Self {
+        let maximum = self.shape.max(UVec3::ONE) - UVec3::ONE;
+
+        self.remapped(self.shape, |position| match axis {
+            Axis3::X => UVec3::new(maximum.x - position.x, position.y, position.z),
+            Axis3::Y => UVec3::new(position.x, maximum.y - position.y, position.z),
+            Axis3::Z => UVec3::new(position.x, position.y, maximum.z - position.z),
+        })
+    }
+
+    fn rotated_once(&self, axis: Axis3) -> Self {
+        let maximum = self.shape.max(UVec3::ONE) - UVec3::ONE;
+
+        match axis {
+            Axis3::X => self.remapped(self.shape.xzy(), |position| {
+                UVec3::new(position.x, maximum.z - position.z, position.y)
+            }),
+            Axis3::Y => self.remapped(self.shape.zyx(), |position| {
+                UVec3::new(position.z, position.y, maximum.x - position.x)
+            }),
+            Axis3::Z => self.remapped(self.shape.yxz(), |position| {
+                UVec3::new(maximum.y - position.y, position.x, position.z)
+            }),
+        }
+    }
+
+    /// Builds a volume of `shape` by moving each voxel to the position returned by `f`.
+    fn remapped(&self, shape: UVec3, f: impl Fn(UVec3) -> UVec3) -> Self {
+        let mut volume = Self::new(shape);
+
+        for (position, voxel) in self.iter() {
+            *volume.voxel_at_mut(f(position)) = voxel;
+        }
+
+        volume
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (UVec3, Voxel)> + '_ {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(i, voxel)| (self.delinearize(i), *voxel))
+    }
+}
@@ -1,48 +1,44 @@
-use block_mesh_pop::{MergeVoxel, MeshVoxel, VoxelVisibility};
-
-use crate::prelude::Color;
-
+/// A block id packed together with the block's state.
+///
+/// The low [`Voxel::ID_BITS`] bits hold the block id and the remaining [`Voxel::STATE_BITS`] bits
+/// hold state whose layout is declared per block in the [`BlockRegistry`](super::BlockRegistry).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Voxel(pub u16);
 
 impl Voxel {
     pub const EMPTY: Self = Self(0);
 
-    pub fn get_color(&self) -> Color {
-        match self {
-            Self(1) => Color::TURQUOISE,
-            Self(2) => Color::DARK_GRAY,
-            Self(3) => Color::GREEN,
-            _ => Color::WHITE,
-        }
-    }
-}
+    pub const ID_BITS: u32 = 10;
+    pub const STATE_BITS: u32 = u16::BITS - Self::ID_BITS;
 
-impl Default for Voxel {
-    fn default() -> Self {
-        Self::EMPTY
+    pub const MAX_ID: u16 = (1 << Self::ID_BITS) - 1;
+    pub const MAX_STATE: u16 = (1 << Self::STATE_BITS) - 1;
+
+    #[inline]
+    pub const fn new(id: u16, state: u16) -> Self {
+        debug_assert!(id <= Self::MAX_ID && state <= Self::MAX_STATE);
+
+        Self((id & Self::MAX_ID) | (state << Self::ID_BITS))
     }
-}
 
-impl MeshVoxel for Voxel {
-    fn get_visibility(&self) -> block_mesh_pop::VoxelVisibility {
-        match *self {
-            Self::EMPTY => VoxelVisibility::Empty,
-            Self(1) => VoxelVisibility::Translucent,
-            _ => VoxelVisibility::Opaque,
-        }
+    #[inline]
+    pub const fn id(self) -> u16 {
+        self.0 & Self::MAX_ID
     }
-}
 
-impl MergeVoxel for Voxel {
-    type MergeValue = Self;
-    type MergeValueFacingNeighbour = Self;
+    #[inline]
+    pub const fn state(self) -> u16 {
+        self.0 >> Self::ID_BITS
+    }
 
-    fn merge_value(&self) -> Self::MergeValue {
-        *self
+    #[inline]
+    pub const fn with_state(self, state: u16) -> Self {
+        Self::new(self.id(), state)
     }
+}
 
-    fn merge_value_facing_neighbour(&self) -> Self::MergeValueFacingNeighbour {
-        *self
+impl Default for Voxel {
+    fn default() -> Self {
+        Self::EMPTY
     }
 }
@@ -1,39 +1,61 @@
 use std::sync::{Arc, RwLock};
 
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
+use ilattice::prelude::Extent;
 
 use crate::prelude::*;
 
+/// Sent when voxels within a world extent are changed through [`VoxelWorld`].
+#[derive(Clone, Copy, Debug, Event)]
+pub struct VoxelsChanged {
+    pub extent: Extent<IVec3>,
+}
+
 #[derive(Resource)]
 pub struct VoxelWorld {
-    chunks: HashMap<IVec3, Arc<RwLock<VoxelChunk>>>,
+    chunks: HashMap<ChunkPos, Arc<RwLock<VoxelChunk>>>,
+    modified: HashSet<ChunkPos>,
+    unsaved: HashSet<ChunkPos>,
+    changes: Vec<Extent<IVec3>>,
 }
 
 impl Default for VoxelWorld {
     fn default() -> Self {
         Self {
             chunks: HashMap::new(),
+            modified: HashSet::new(),
+            unsaved: HashSet::new(),
+            changes: Vec::new(),
         }
     }
 }
 
 impl VoxelWorld {
-    pub fn get(&self, position: &IVec3) -> Option<Arc<RwLock<VoxelChunk>>> {
+    pub fn get(&self, position: &ChunkPos) -> Option<Arc<RwLock<VoxelChunk>>> {
         self.chunks.get(position).cloned()
     }
 
-    pub fn insert(&mut self, position: IVec3, chunk: VoxelChunk) {
+    /// Inserts a chunk, bringing its padding and the padding of its loaded neighbours in sync
+    /// with each other.
+    pub fn insert(&mut self, position: ChunkPos, chunk: VoxelChunk) {
         self.chunks.insert(position, Arc::new(RwLock::new(chunk)));
+        self.sync_padding(position);
     }
 
-    pub fn contains(&self, position: &IVec3) -> bool {
+    pub fn contains(&self, position: &ChunkPos) -> bool {
         self.chunks.contains_key(position)
     }
 
-    pub fn remove(&mut self, position: &IVec3) -> Option<Arc<RwLock<VoxelChunk>>> {
+    pub fn remove(&mut self, position: &ChunkPos) -> Option<Arc<RwLock<VoxelChunk>>> {
+        self.modified.remove(position);
+        self.unsaved.remove(position);
         self.chunks.remove(position)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&ChunkPos, &Arc<RwLock<VoxelChunk>>)> + '_ {
+        self.chunks.iter()
+    }
+
     pub fn len(&self) -> usize {
         self.chunks.len()
     }
@@ -41,4 +63,313 @@ impl VoxelWorld {
     pub fn is_empty(&self) -> bool {
         self.chunks.is_empty()
     }
+
+    /// Returns the chunks whose voxels or padding have changed since the last call.
+    pub fn drain_modified(&mut self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.modified.drain()
+    }
+
+    /// Marks a chunk as differing from its stored copy, such as after it is generated.
+    pub fn mark_unsaved(&mut self, position: ChunkPos) {
+        if self.chunks.contains_key(&position) {
+            self.unsaved.insert(position);
+        }
+    }
+
+    /// Returns whether a chunk has changed since it was last saved.
+    pub fn is_unsaved(&self, position: &ChunkPos) -> bool {
+        self.unsaved.contains(position)
+    }
+
+    /// Returns the chunks that have changed since they were last saved.
+    pub fn unsaved(&self) -> impl Iterator<Item = &ChunkPos> + '_ {
+        self.unsaved.iter()
+    }
+
+    /// Clears the unsaved mark of a chunk that is about to be saved, returning whether it was
+    /// set. Changes made after this mark the chunk again.
+    pub fn mark_saved(&mut self, position: &ChunkPos) -> bool {
+        self.unsaved.remove(position)
+    }
+
+    /// Returns the world extents changed since the last call.
+    pub fn drain_changes(&mut self) -> impl Iterator<Item = Extent<IVec3>> + '_ {
+        self.changes.drain(..)
+    }
+
+    /// Returns the voxel at a world position, or `None` if its chunk is not loaded.
+    pub fn get_voxel(&self, position: WorldVoxelPos) -> Option<Voxel> {
+        let (chunk_position, local) = position.split();
+
+        self.chunks
+            .get(&chunk_position)
+            .map(|chunk| chunk.read().unwrap().voxels.voxel_at(local.padded()))
+    }
+
+    /// Sets the voxel at a world position, returning `false` if its chunk is not loaded.
+    pub fn set_voxel(&mut self, position: WorldVoxelPos, voxel: Voxel) -> bool {
+        if !self.chunks.contains_key(&position.chunk()) {
+            return false;
+        }
+
+        self.set_voxels(position, &VoxelVolume::filled(UVec3::ONE, voxel));
+
+        true
+    }
+
+    /// Copies the voxels within a world extent into a standalone volume. Voxels in chunks that are
+    /// not loaded are left empty.
+    pub fn get_voxels(&self, extent: Extent<IVec3>) -> VoxelVolume {
+        let mut volume = VoxelVolume::new(extent.shape.max(IVec3::ZERO).as_uvec3());
+
+        for (chunk_position, chunk_extent) in chunks_in(extent, 0) {
+            let Some(chunk) = self.chunks.get(&chunk_position) else {
+                continue;
+            };
+            let padded_origin = chunk_position.padded_origin().0;
+            let chunk = chunk.read().unwrap();
+
+            for_each_position(chunk_extent, |position| {
+                *volume.voxel_at_mut((position - extent.minimum).as_uvec3()) =
+                    chunk.voxels.voxel_at((position - padded_origin).as_uvec3());
+            });
+        }
+
+        volume
+    }
+
+    /// Sets every voxel within a world extent to `voxel`. Voxels in chunks that are not loaded
+    /// are skipped.
+    pub fn fill_voxels(&mut self, extent: Extent<IVec3>, voxel: Voxel) {
+        let mut any_changed = false;
+
+        for (chunk_position, chunk_extent) in chunks_in(extent, 1) {
+            let Some(chunk) = self.chunks.get(&chunk_position) else {
+                continue;
+            };
+            let padded_origin = chunk_position.padded_origin().0;
+
+            chunk.write().unwrap().voxels.fill_extent(
+                Extent::from_min_and_shape(
+                    (chunk_extent.minimum - padded_origin).as_uvec3(),
+                    chunk_extent.shape.as_uvec3(),
+                ),
+                voxel,
+            );
+            self.modified.insert(chunk_position);
+            self.unsaved.insert(chunk_position);
+            any_changed = true;
+        }
+
+        if any_changed {
+            self.changes.push(extent);
+        }
+    }
+
+    /// Sets every voxel within a world extent to the value returned by `f` for its position.
+    /// Voxels in chunks that are not loaded are skipped.
+    pub fn set_voxels_with(
+        &mut self,
+        extent: Extent<IVec3>,
+        mut f: impl FnMut(WorldVoxelPos) -> Voxel,
+    ) {
+        let mut volume = VoxelVolume::new(extent.shape.max(IVec3::ZERO).as_uvec3());
+
+        for z in 0..volume.shape().z {
+            for y in 0..volume.shape().y {
+                for x in 0..volume.shape().x {
+                    let offset = UVec3::new(x, y, z);
+                    *volume.voxel_at_mut(offset) =
+                        f(WorldVoxelPos(extent.minimum + offset.as_ivec3()));
+                }
+            }
+        }
+
+        self.set_voxels(WorldVoxelPos(extent.minimum), &volume);
+    }
+
+    /// Copies a volume into the world with its minimum corner at `minimum`. Voxels in chunks that
+    /// are not loaded are skipped.
+    ///
+    /// Voxels on a chunk boundary are also written into the padding of the neighbouring chunks,
+    /// and every chunk that changes is marked as modified.
+    pub fn set_voxels(&mut self, minimum: WorldVoxelPos, volume: &VoxelVolume) {
+        self.write_volume(minimum, volume, false);
+    }
+
+    /// Copies the non-empty voxels of a volume into the world with its minimum corner at
+    /// `minimum`, leaving the world untouched where the volume is empty. See
+    /// [`VoxelWorld::set_voxels`].
+    pub fn merge_voxels(&mut self, minimum: WorldVoxelPos, volume: &VoxelVolume) {
+        self.write_volume(minimum, volume, true);
+    }
+
+    fn write_volume(&mut self, minimum: WorldVoxelPos, volume: &VoxelVolume, skip_empty: bool) {
+        let extent = Extent::from_min_and_shape(minimum.0, volume.shape().as_ivec3());
+        let mut any_changed = false;
+
+        for (chunk_position, chunk_extent) in chunks_in(extent, 1) {
+            let Some(chunk) = self.chunks.get(&chunk_position) else {
+                continue;
+            };
+            let padded_origin = chunk_position.padded_origin().0;
+            let mut chunk = chunk.write().unwrap();
+            let mut changed = false;
+
+            for_each_position(chunk_extent, |position| {
+                let voxel = volume.voxel_at((position - minimum.0).as_uvec3());
+                let local = (position - padded_origin).as_uvec3();
+
+                if skip_empty && voxel == Voxel::EMPTY {
+                    return;
+                }
+
+                if chunk.voxels.voxel_at(local) != voxel {
+                    chunk.voxels.set_voxel_at(local, voxel);
+                    changed = true;
+                }
+            });
+
+            if changed {
+                self.modified.insert(chunk_position);
+                self.unsaved.insert(chunk_position);
+                any_changed = true;
+            }
+        }
+
+        if any_changed {
+            self.changes.push(extent);
+        }
+    }
+
+    /// Copies the voxels shared between a chunk and each of its loaded neighbours in both
+    /// directions, so that the padding of each matches the voxels of the other.
+    fn sync_padding(&mut self, position: ChunkPos) {
+        let Some(chunk) = self.chunks.get(&position) else {
+            return;
+        };
+
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let offset = IVec3::new(x, y, z);
+                    if offset == IVec3::ZERO {
+                        continue;
+                    }
+
+                    let neighbour_position = position + offset;
+                    let Some(neighbour) = self.chunks.get(&neighbour_position) else {
+                        continue;
+                    };
+
+                    copy_shared(
+                        &neighbour.read().unwrap(),
+                        neighbour_position,
+                        &mut chunk.write().unwrap(),
+                        position,
+                    );
+
+                    if copy_shared(
+                        &chunk.read().unwrap(),
+                        position,
+                        &mut neighbour.write().unwrap(),
+                        neighbour_position,
+                    ) {
+                        self.modified.insert(neighbour_position);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the world extent covered by a chunk, grown by `padding` voxels on each side.
+fn chunk_extent(position: ChunkPos, padding: i32) -> Extent<IVec3> {
+    Extent::from_min_and_shape(
+        position.origin().0 - IVec3::splat(padding),
+        IVec3::splat(CHUNK_SIZE as i32 + padding * 2),
+    )
+}
+
+fn intersection(a: Extent<IVec3>, b: Extent<IVec3>) -> Extent<IVec3> {
+    let minimum = a.minimum.max(b.minimum);
+    let least_upper_bound = a.least_upper_bound().min(b.least_upper_bound());
+
+    Extent::from_min_and_lub(minimum, least_upper_bound.max(minimum))
+}
+
+/// Copies the voxels of `source` that lie within the padding of `target`, returning whether any
+/// voxel in `target` changed.
+fn copy_shared(
+    source: &VoxelChunk,
+    source_position: ChunkPos,
+    target: &mut VoxelChunk,
+    target_position: ChunkPos,
+) -> bool {
+    let extent = intersection(
+        chunk_extent(source_position, 0),
+        chunk_extent(target_position, 1),
+    );
+    let source_origin = source_position.padded_origin().0;
+    let target_origin = target_position.padded_origin().0;
+    let mut changed = false;
+
+    for_each_position(extent, |position| {
+        let voxel = source
+            .voxels
+            .voxel_at((position - source_origin).as_uvec3());
+        let local = (position - target_origin).as_uvec3();
+
+        if target.voxels.voxel_at(local) != voxel {
+            target.voxels.set_voxel_at(local, voxel);
+            changed = true;
+        }
+    });
+
+    changed
+}
+
+/// Returns each chunk whose extent, grown by `padding` voxels on each side, overlaps a world
+/// extent, along with the part of the extent inside it.
+fn chunks_in(extent: Extent<IVec3>, padding: i32) -> Vec<(ChunkPos, Extent<IVec3>)> {
+    if extent.shape.cmple(IVec3::ZERO).any() {
+        return Vec::new();
+    }
+
+    let minimum_chunk = WorldVoxelPos(extent.minimum - IVec3::splat(padding))
+        .chunk()
+        .0;
+    let maximum_chunk =
+        WorldVoxelPos(extent.least_upper_bound() - IVec3::ONE + IVec3::splat(padding))
+            .chunk()
+            .0;
+
+    let mut chunks = Vec::new();
+
+    for z in minimum_chunk.z..=maximum_chunk.z {
+        for y in minimum_chunk.y..=maximum_chunk.y {
+            for x in minimum_chunk.x..=maximum_chunk.x {
+                let chunk_position = ChunkPos::new(x, y, z);
+
+                chunks.push((
+                    chunk_position,
+                    intersection(extent, chunk_extent(chunk_position, padding)),
+                ));
+            }
+        }
+    }
+
+    chunks
+}
+
+fn for_each_position(extent: Extent<IVec3>, mut f: impl FnMut(IVec3)) {
+    let least_upper_bound = extent.least_upper_bound();
+
+    for z in extent.minimum.z..least_upper_bound.z {
+        for y in extent.minimum.y..least_upper_bound.y {
+            for x in extent.minimum.x..least_upper_bound.x {
+                f(IVec3::new(x, y, z));
+            }
+        }
+    }
 }
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, RwLock},
+};
+
+use bevy::{
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use futures_lite::future::{block_on, poll_once};
+
+use crate::{
+    persistence::codec::{decode_chunk, encode_chunk},
+    prelude::*,
+};
+
+/// The default size of the compressed chunks kept by a [`DroppedChunkCache`].
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+enum CachedData {
+    /// The chunk is being compressed in the background.
+    Compressing {
+        chunk: Arc<RwLock<VoxelChunk>>,
+        task: Task<io::Result<Vec<u8>>>,
+    },
+    Compressed(Vec<u8>),
+}
+
+struct CachedChunk {
+    data: CachedData,
+    last_used: u64,
+}
+
+impl CachedChunk {
+    /// Returns the number of bytes the chunk counts against the memory budget. Chunks still being
+    /// compressed count once they are compressed.
+    fn memory_used(&self) -> usize {
+        match &self.data {
+            CachedData::Compressing { .. } => 0,
+            CachedData::Compressed(data) => data.len(),
+        }
+    }
+}
+
+/// Recently dropped chunks, compressed, so that chunks dropped and loaded again while moving
+/// back and forth are neither read from disk nor generated again.
+///
+/// Only chunks whose changes have been saved are cached, so evicting one never loses data; it is
+/// read back from disk the next time it is loaded. The least recently dropped chunks are evicted
+/// once the cache exceeds its memory budget.
+///
+/// Chunks are compressed and decompressed on the [`AsyncComputeTaskPool`], so dropping and
+/// loading many chunks at once never stalls the frame.
+#[derive(Resource)]
+pub struct DroppedChunkCache {
+    chunks: HashMap<ChunkPos, CachedChunk>,
+    order: BTreeMap<u64, ChunkPos>,
+    compressing: HashSet<ChunkPos>,
+    next_use: u64,
+    memory_budget: usize,
+    memory_used: usize,
+}
+
+impl Default for DroppedChunkCache {
+    fn default() -> Self {
+        Self::with_memory_budget(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl DroppedChunkCache {
+    pub fn with_memory_budget(memory_budget: usize) -> Self {
+        Self {
+            chunks: HashMap::default(),
+            order: BTreeMap::new(),
+            compressing: HashSet::default(),
+            next_use: 0,
+            memory_budget,
+            memory_used: 0,
+        }
+    }
+
+    #[inline]
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.enforce_budget();
+    }
+
+    /// Returns the number of bytes of compressed chunks currently cached.
+    #[inline]
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, position: &ChunkPos) -> bool {
+        self.chunks.contains_key(position)
+    }
+
+    /// Caches a dropped chunk, replacing any older copy, and starts compressing it. The chunk
+    /// must already be saved.
+    pub fn insert(&mut self, position: ChunkPos, chunk: Arc<RwLock<VoxelChunk>>) {
+        self.remove(&position);
+
+        let task = AsyncComputeTaskPool::get().spawn({
+            let chunk = chunk.clone();
+            async move { encode_chunk(&chunk.read().unwrap()) }
+        });
+
+        let last_used = self.next_use;
+        self.next_use += 1;
+
+        self.order.insert(last_used, position);
+        self.compressing.insert(position);
+        self.chunks.insert(
+            position,
+            CachedChunk {
+                data: CachedData::Compressing { chunk, task },
+                last_used,
+            },
+        );
+    }
+
+    /// Removes a chunk from the cache, returning a task that decompresses it.
+    pub fn take(&mut self, position: &ChunkPos) -> Option<Task<io::Result<VoxelChunk>>> {
+        let cached = self.remove(position)?;
+        let thread_pool = AsyncComputeTaskPool::get();
+
+        Some(match cached.data {
+            // Dropping the task cancels the compression, though it may still hold the chunk.
+            CachedData::Compressing { chunk, .. } => thread_pool.spawn(async move {
+                Ok(match Arc::try_unwrap(chunk) {
+                    Ok(chunk) => chunk.into_inner().unwrap(),
+                    Err(chunk) => chunk.read().unwrap().clone(),
+                })
+            }),
+            CachedData::Compressed(data) => thread_pool.spawn(async move { decode_chunk(&data) }),
+        })
+    }
+
+    /// Stores the chunks that have finished compressing, evicting chunks if the cache no longer
+    /// fits its budget.
+    pub fn poll_tasks(&mut self) {
+        if self.compressing.is_empty() {
+            return;
+        }
+
+        let mut compressed = Vec::new();
+        for position in &self.compressing {
+            let Some(CachedChunk {
+                data: CachedData::Compressing { task, .. },
+                ..
+            }) = self.chunks.get_mut(position)
+            else {
+                continue;
+            };
+
+            if let Some(result) = block_on(poll_once(task)) {
+                compressed.push((*position, result));
+            }
+        }
+
+        for (position, result) in compressed {
+            self.compressing.remove(&position);
+
+            match result {
+                Ok(data) => {
+                    self.memory_used += data.len();
+                    self.chunks.get_mut(&position).unwrap().data = CachedData::Compressed(data);
+                }
+                Err(err) => {
+                    warn!("failed to cache chunk {position}: {err}");
+                    self.remove(&position);
+                }
+            }
+        }
+
+        self.enforce_budget();
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.order.clear();
+        self.compressing.clear();
+        self.memory_used = 0;
+    }
+
+    fn remove(&mut self, position: &ChunkPos) -> Option<CachedChunk> {
+        let cached = self.chunks.remove(position)?;
+
+        self.order.remove(&cached.last_used);
+        self.compressing.remove(position);
+        self.memory_used -= cached.memory_used();
+
+        Some(cached)
+    }
+
+    /// Evicts the least recently dropped chunks until the cache fits its budget.
+    fn enforce_budget(&mut self) {
+        while self.memory_used > self.memory_budget {
+            let Some((_, position)) = self.order.pop_first() else {
+                break;
+            };
+
+            if let Some(cached) = self.chunks.remove(&position) {
+                self.compressing.remove(&position);
+                self.memory_used -= cached.memory_used();
+            }
+        }
+    }
+}
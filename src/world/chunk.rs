@@ -1,40 +1,70 @@
-use bevy::{
-    math::{Vec3A, Vec3Swizzles},
-    render::primitives::Aabb,
-    utils::HashMap,
-};
-use block_mesh_pop::{LodEasing, LodMaterial, WrappedMaterial};
+use std::io;
+
+use bevy::{math::Vec3A, render::primitives::Aabb, tasks::Task, utils::HashMap};
+use futures_lite::future::{block_on, poll_once};
 
 use crate::{
-    generation::chunk::ChunkGenerationQueue, prelude::*, render::mesh::chunk::MeshChunkQueue,
+    generation::chunk::ChunkGenerationQueue,
+    persistence::{
+        save::{SaveChunkQueue, SaveTask, UnloadedChunks},
+        ChunkStorage,
+    },
+    prelude::*,
+    render::mesh::chunk::MeshChunkQueue,
 };
 
-use super::heightmap::{HeightmapEntityMap, HeightmapMarker};
+use super::{
+    cache::DroppedChunkCache,
+    heightmap::{HeightmapEntityMap, HeightmapMarker},
+};
 
 pub struct WorldChunkPlugin;
 
 impl Plugin for WorldChunkPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ChunkEntityMap>()
+            .init_resource::<DroppedChunkCache>()
             .init_resource::<LoadChunkQueue>()
             .init_resource::<DropChunkQueue>()
-            .add_systems(Update, (handle_load_chunk_queue, handle_drop_chunk_queue));
+            .add_event::<ChunkLoaded>()
+            .add_event::<ChunkUnloaded>()
+            .add_systems(
+                Update,
+                (
+                    handle_load_chunk_queue,
+                    handle_drop_chunk_queue,
+                    handle_cached_chunk_tasks,
+                    handle_cache_tasks,
+                ),
+            );
     }
 }
 
+/// Sent when a chunk is restored from storage instead of being generated.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct ChunkLoaded {
+    pub position: ChunkPos,
+}
+
+/// Sent when a chunk has been removed from the [`VoxelWorld`] and its entity despawned.
+#[derive(Clone, Copy, Debug, Event)]
+pub struct ChunkUnloaded {
+    pub position: ChunkPos,
+}
+
 #[derive(Component)]
 pub struct Chunk {
-    pub position: IVec3,
+    pub position: ChunkPos,
     pub is_loaded: bool,
     pub lod: usize,
 }
 
+/// A chunk's entity. Its materials are added along with its first non-empty mesh, so chunks
+/// with nothing to draw never allocate them.
 #[derive(Bundle)]
 pub struct ChunkBundle {
     chunk: Chunk,
     mesh: Handle<Mesh>,
-    material: WrappedMaterial<StandardMaterial>,
-    lod: Handle<LodMaterial<6>>,
     transform: Transform,
     global_transform: GlobalTransform,
     visibility: Visibility,
@@ -43,12 +73,7 @@ pub struct ChunkBundle {
 }
 
 impl ChunkBundle {
-    fn new(
-        position: IVec3,
-        mesh: Handle<Mesh>,
-        material: Handle<StandardMaterial>,
-        lod: Handle<LodMaterial<6>>,
-    ) -> Self {
+    fn new(position: ChunkPos, mesh: Handle<Mesh>) -> Self {
         Self {
             chunk: Chunk {
                 position,
@@ -56,9 +81,7 @@ impl ChunkBundle {
                 lod: 0,
             },
             mesh,
-            material: material.into(),
-            lod,
-            transform: Transform::from_translation((position * CHUNK_SIZE as i32 - 1).as_vec3()),
+            transform: Transform::from_translation(position.padded_origin().0.as_vec3()),
             global_transform: GlobalTransform::default(),
             visibility: Visibility::default(),
             computed_visibility: ComputedVisibility::default(),
@@ -72,102 +95,204 @@ impl ChunkBundle {
 
 #[derive(Default, Resource)]
 pub struct ChunkEntityMap {
-    map: HashMap<IVec3, Entity>,
+    map: HashMap<ChunkPos, Entity>,
 }
 
 impl ChunkEntityMap {
-    pub fn insert(&mut self, position: IVec3, entity: Entity) {
+    pub fn insert(&mut self, position: ChunkPos, entity: Entity) {
         self.map.insert(position, entity);
     }
 
-    pub fn get(&self, position: &IVec3) -> Option<Entity> {
+    pub fn get(&self, position: &ChunkPos) -> Option<Entity> {
         self.map.get(position).cloned()
     }
 
-    pub fn contains(&self, position: &IVec3) -> bool {
+    pub fn contains(&self, position: &ChunkPos) -> bool {
         self.map.contains_key(position)
     }
 
-    pub fn remove(&mut self, position: &IVec3) -> Option<Entity> {
+    pub fn remove(&mut self, position: &ChunkPos) -> Option<Entity> {
         self.map.remove(position)
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &IVec3> + '_ {
+    pub fn keys(&self) -> impl Iterator<Item = &ChunkPos> + '_ {
         self.map.keys()
     }
 }
 
+/// Restores a chunk from the [`DroppedChunkCache`] in the background.
+#[derive(Component)]
+pub struct CachedChunkTask {
+    task: Task<io::Result<VoxelChunk>>,
+}
+
 pub struct LoadChunk;
 pub struct DropChunk;
 
-pub type LoadChunkQueue = UnorderedQueue<IVec3, LoadChunk>;
-pub type DropChunkQueue = UnorderedQueue<IVec3, DropChunk>;
+pub type LoadChunkQueue = UnorderedQueue<ChunkPos, LoadChunk>;
+pub type DropChunkQueue = UnorderedQueue<ChunkPos, DropChunk>;
 
+#[allow(clippy::too_many_arguments)]
 fn handle_load_chunk_queue(
     mut commands: Commands,
-    mut lod_materials: ResMut<Assets<LodMaterial<6>>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut entity_map: ResMut<ChunkEntityMap>,
     mut queue: ResMut<LoadChunkQueue>,
-    world: Res<VoxelWorld>,
+    mut world: ResMut<VoxelWorld>,
+    storage: Res<ChunkStorage>,
+    mut unloaded: ResMut<UnloadedChunks>,
+    mut cache: ResMut<DroppedChunkCache>,
     mut chunk_gen_queue: ResMut<ChunkGenerationQueue>,
     mut chunk_mesh_queue: ResMut<MeshChunkQueue>,
+    mut loaded_events: EventWriter<ChunkLoaded>,
 ) {
     for position in queue.drain(..) {
         if !entity_map.map.contains_key(&position) {
-            let material = materials.add(StandardMaterial::from(Color::rgb(1.0, 1.0, 1.0)));
-            let lod_material = lod_materials.add(LodMaterial {
-                size: UVec3::splat(64),
-                max_lod: 6,
-                period: 1024,
-                easing: LodEasing::Sine,
-                buckets: [UVec4::ZERO; 2],
-            });
             let entity = commands
-                .spawn(ChunkBundle::new(
-                    position,
-                    Handle::default(),
-                    material,
-                    lod_material,
-                ))
+                .spawn(ChunkBundle::new(position, Handle::default()))
                 .id();
 
             entity_map.map.insert(position, entity);
 
-            if !world.contains(&position) {
-                chunk_gen_queue.push(position);
-            } else {
+            if world.contains(&position) {
+                chunk_mesh_queue.push(position);
+                continue;
+            }
+
+            // A chunk that was unloaded before it was saved is newer than its copy on disk.
+            if let Some(chunk) = unloaded.take(&position) {
+                world.insert(position, chunk);
+                world.mark_unsaved(position);
+                chunk_mesh_queue.push(position);
+                loaded_events.send(ChunkLoaded { position });
+                continue;
+            }
+
+            if let Some(task) = cache.take(&position) {
+                commands.entity(entity).insert(CachedChunkTask { task });
+                continue;
+            }
+
+            load_from_storage(
+                position,
+                &mut world,
+                &storage,
+                &mut chunk_gen_queue,
+                &mut chunk_mesh_queue,
+                &mut loaded_events,
+            );
+        }
+    }
+}
+
+/// Inserts the chunks that have been restored from the [`DroppedChunkCache`] into the world.
+fn handle_cached_chunk_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &Chunk, &mut CachedChunkTask)>,
+    mut world: ResMut<VoxelWorld>,
+    storage: Res<ChunkStorage>,
+    mut chunk_gen_queue: ResMut<ChunkGenerationQueue>,
+    mut chunk_mesh_queue: ResMut<MeshChunkQueue>,
+    mut loaded_events: EventWriter<ChunkLoaded>,
+) {
+    for (entity, chunk, mut task) in &mut tasks {
+        let Some(result) = block_on(poll_once(&mut task.task)) else {
+            continue;
+        };
+
+        commands.entity(entity).remove::<CachedChunkTask>();
+
+        let position = chunk.position;
+        match result {
+            Ok(chunk) => {
+                world.insert(position, chunk);
                 chunk_mesh_queue.push(position);
+                loaded_events.send(ChunkLoaded { position });
             }
+            Err(err) => {
+                warn!("failed to decode cached chunk {position}: {err}");
+                load_from_storage(
+                    position,
+                    &mut world,
+                    &storage,
+                    &mut chunk_gen_queue,
+                    &mut chunk_mesh_queue,
+                    &mut loaded_events,
+                );
+            }
+        }
+    }
+}
+
+/// Loads a chunk from storage, or queues it to be generated if it was never saved.
+fn load_from_storage(
+    position: ChunkPos,
+    world: &mut VoxelWorld,
+    storage: &ChunkStorage,
+    chunk_gen_queue: &mut ChunkGenerationQueue,
+    chunk_mesh_queue: &mut MeshChunkQueue,
+    loaded_events: &mut EventWriter<ChunkLoaded>,
+) {
+    match storage.get().load_chunk(position) {
+        Ok(Some(chunk)) => {
+            world.insert(position, chunk);
+            chunk_mesh_queue.push(position);
+            loaded_events.send(ChunkLoaded { position });
+        }
+        Ok(None) => chunk_gen_queue.push(position),
+        Err(err) => {
+            warn!("failed to load chunk {position}, regenerating: {err}");
+            chunk_gen_queue.push(position);
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_drop_chunk_queue(
     mut commands: Commands,
     mut entity_map: ResMut<ChunkEntityMap>,
     mut queue: ResMut<DropChunkQueue>,
     mut world: ResMut<VoxelWorld>,
+    mut unloaded: ResMut<UnloadedChunks>,
+    mut cache: ResMut<DroppedChunkCache>,
+    mut save_queue: ResMut<SaveChunkQueue>,
+    save_task: Res<SaveTask>,
     mut chunk_gen_queue: ResMut<ChunkGenerationQueue>,
     mut chunk_mesh_queue: ResMut<MeshChunkQueue>,
     heightmap_entity_map: Res<HeightmapEntityMap>,
     mut heightmaps: Query<&mut HeightmapMarker>,
+    mut unloaded_events: EventWriter<ChunkUnloaded>,
 ) {
     for position in queue.drain(..) {
         chunk_gen_queue.remove(&position);
         chunk_mesh_queue.remove(&position);
 
         if let Some(entity) = entity_map.map.remove(&position) {
+            // Hand unsaved chunks to the save queue, and keep chunks that are being saved
+            // until the save finishes so they are not reloaded from disk in the meantime.
+            // Saved chunks are cached in case they are loaded again soon.
+            let unsaved = world.is_unsaved(&position);
             if let Some(chunk) = world.remove(&position) {
-                // TODO: save
+                if unsaved || save_task.contains(&position) {
+                    unloaded.insert(position, chunk, unsaved);
+                } else {
+                    cache.insert(position, chunk);
+                }
+                if unsaved {
+                    save_queue.push(position);
+                }
             }
 
             commands.entity(entity).despawn();
+            unloaded_events.send(ChunkUnloaded { position });
         }
-        if let Some(entity) = heightmap_entity_map.get(&position.xz()) {
+        if let Some(entity) = heightmap_entity_map.get(&position.column()) {
             if let Ok(mut heightmap) = heightmaps.get_mut(entity) {
                 heightmap.blocking.remove(&position);
             }
         }
     }
 }
+
+fn handle_cache_tasks(mut cache: ResMut<DroppedChunkCache>) {
+    cache.poll_tasks();
+}
@@ -26,10 +26,10 @@ impl Plugin for WorldHeightmapPlugin {
 
 #[derive(Component)]
 pub struct HeightmapMarker {
-    pub position: IVec2,
+    pub position: ColumnPos,
     pub minimum: i32,
     pub maximum: i32,
-    pub blocking: HashSet<IVec3>,
+    pub blocking: HashSet<ChunkPos>,
 }
 
 #[derive(Bundle)]
@@ -45,7 +45,7 @@ pub struct HeightmapBundle {
 }
 
 impl HeightmapBundle {
-    fn new(position: IVec2, mesh: Handle<Mesh>, material: Handle<StandardMaterial>) -> Self {
+    fn new(position: ColumnPos, mesh: Handle<Mesh>, material: Handle<StandardMaterial>) -> Self {
         Self {
             heightmap: HeightmapMarker {
                 position,
@@ -55,9 +55,7 @@ impl HeightmapBundle {
             },
             mesh,
             material,
-            transform: Transform::from_translation(
-                (position * CHUNK_SIZE as i32 - 1).extend_y(0).as_vec3(),
-            ),
+            transform: Transform::from_translation(position.padded_origin().extend_y(0).as_vec3()),
             global_transform: GlobalTransform::default(),
             visibility: Visibility::default(),
             computed_visibility: ComputedVisibility::default(),
@@ -79,27 +77,27 @@ impl HeightmapBundle {
 
 #[derive(Default, Resource)]
 pub struct HeightmapEntityMap {
-    map: HashMap<IVec2, Entity>,
+    map: HashMap<ColumnPos, Entity>,
 }
 
 impl HeightmapEntityMap {
-    pub fn insert(&mut self, position: IVec2, entity: Entity) {
+    pub fn insert(&mut self, position: ColumnPos, entity: Entity) {
         self.map.insert(position, entity);
     }
 
-    pub fn get(&self, position: &IVec2) -> Option<Entity> {
+    pub fn get(&self, position: &ColumnPos) -> Option<Entity> {
         self.map.get(position).cloned()
     }
 
-    pub fn contains(&self, position: &IVec2) -> bool {
+    pub fn contains(&self, position: &ColumnPos) -> bool {
         self.map.contains_key(position)
     }
 
-    pub fn remove(&mut self, position: &IVec2) -> Option<Entity> {
+    pub fn remove(&mut self, position: &ColumnPos) -> Option<Entity> {
         self.map.remove(position)
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &IVec2> + '_ {
+    pub fn keys(&self) -> impl Iterator<Item = &ColumnPos> + '_ {
         self.map.keys()
     }
 }
@@ -107,8 +105,8 @@ impl HeightmapEntityMap {
 pub struct LoadHeightmap;
 pub struct DropHeightmap;
 
-pub type LoadHeightmapQueue = UnorderedQueue<IVec2, LoadHeightmap>;
-pub type DropHeightmapQueue = UnorderedQueue<IVec2, DropHeightmap>;
+pub type LoadHeightmapQueue = UnorderedQueue<ColumnPos, LoadHeightmap>;
+pub type DropHeightmapQueue = UnorderedQueue<ColumnPos, DropHeightmap>;
 
 fn handle_load_chunk_queue(
     mut commands: Commands,
@@ -152,16 +150,20 @@ fn handle_blocking(
     mut heightmaps: Query<(&HeightmapMarker, &mut Visibility), Changed<HeightmapMarker>>,
 ) {
     for (heightmap, mut visibility) in &mut heightmaps {
-        let maximum_chunk =
-            (heightmap.maximum.saturating_add(CHUNK_SIZE as i32 - 1)) / CHUNK_SIZE as i32;
-        let minimum_chunk =
-            (heightmap.minimum.saturating_add(CHUNK_SIZE as i32 - 1)) / CHUNK_SIZE as i32;
+        let maximum_chunk = heightmap
+            .maximum
+            .saturating_add(CHUNK_SIZE as i32 - 1)
+            .div_euclid(CHUNK_SIZE as i32);
+        let minimum_chunk = heightmap
+            .minimum
+            .saturating_add(CHUNK_SIZE as i32 - 1)
+            .div_euclid(CHUNK_SIZE as i32);
 
         let mut required_to_block = maximum_chunk.saturating_sub(minimum_chunk) as u32 + 1;
 
-        for position in &heightmap.blocking {
-            if ((position.y + 1) * CHUNK_SIZE as i32) > heightmap.minimum
-                && (position.y * CHUNK_SIZE as i32) < heightmap.maximum
+        for &position in &heightmap.blocking {
+            if (position + IVec3::Y).origin().0.y > heightmap.minimum
+                && position.origin().0.y < heightmap.maximum
             {
                 required_to_block = required_to_block.saturating_sub(1);
             }